@@ -136,6 +136,17 @@ pub unsafe trait EnumLike: Copy {
     /// The number of variants of this type
     const NUM_VARIANTS: usize;
 
+    /// The minimum number of bits used to store this type in an `EnumVec`.
+    ///
+    /// By default, an `EnumVec` uses the minimum number of bits needed to
+    /// store `NUM_VARIANTS` different values, so adding a new variant may
+    /// change the layout of every stored vector. Setting this to a larger
+    /// value keeps the layout stable while the type grows. Using a value
+    /// smaller than needed results in a compile error when the type is used
+    /// inside an `EnumVec`. With `#[derive(EnumLike)]` this can be set using
+    /// `#[enum_like(reserve_bits = 4)]`.
+    const RESERVED_BITS: usize = 0;
+
     /// Convert type to discriminant
     fn to_discr(self) -> usize;
 
@@ -644,6 +655,75 @@ impl<T: EnumLike> Iterator for Values<T> {
 
 impl<T: EnumLike> ExactSizeIterator for Values<T> {}
 
+/// Maps each value of `Self` to a `char`, which is used to display and parse
+/// vectors of `Self` as strings.
+///
+/// Every value must have a different symbol. Using `enum_like_derive`, it
+/// is implemented for enums with unit variants when each variant has a
+/// symbol:
+///
+/// ```ignore
+/// #[derive(Copy, Clone, Debug, EnumLike)]
+/// enum Nucleotide {
+///     #[enum_like(symbol = 'A')]
+///     Adenine,
+///     #[enum_like(symbol = 'C')]
+///     Cytosine,
+///     #[enum_like(symbol = 'G')]
+///     Guanine,
+///     #[enum_like(symbol = 'T')]
+///     Thymine,
+/// }
+///
+/// assert_eq!(Nucleotide::Guanine.symbol(), 'G');
+/// ```
+pub trait EnumSymbol: EnumLike {
+    /// The symbol of this value
+    fn symbol(self) -> char;
+}
+
+impl EnumSymbol for bool {
+    fn symbol(self) -> char {
+        if self {
+            '1'
+        } else {
+            '0'
+        }
+    }
+}
+
+/// A static table with every value of `Self`, which allows containers of
+/// packed values to return references, for example to implement `Index`.
+///
+/// `ALL[x]` must be equal to `Self::from_discr(x)`, and `ALL` must have
+/// `NUM_VARIANTS` elements. Using `enum_like_derive`, it is implemented for
+/// enums with unit variants with the `static_table` option:
+///
+/// ```ignore
+/// #[derive(Copy, Clone, Debug, EnumLike)]
+/// #[enum_like(static_table)]
+/// enum Direction {
+///     North,
+///     East,
+///     South,
+///     West,
+/// }
+///
+/// assert_eq!(Direction::ALL[2], Direction::South);
+/// ```
+pub trait EnumTable: EnumLike + 'static {
+    /// All the values, in discriminant order
+    const ALL: &'static [Self];
+}
+
+impl EnumTable for () {
+    const ALL: &'static [()] = &[()];
+}
+
+impl EnumTable for bool {
+    const ALL: &'static [bool] = &[false, true];
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -935,4 +1015,8 @@ mod tests {
         let b_p8 = PackedU8::new(b);
     }
     */
+
+    // A RESERVED_BITS smaller than needed is a compile error in EnumVec, which
+    // is checked by the compile_fail doctest of EnumVec::BITS_PER_ELEM in the
+    // enum_vec crate
 }
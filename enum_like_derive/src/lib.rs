@@ -22,8 +22,9 @@ use quote::Tokens;
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
 use syn::{
-    Data, DataEnum, DataStruct, DeriveInput, Field, Fields, GenericParam,
-    Generics, Ident, Variant,
+    Attribute, Data, DataEnum, DataStruct, DeriveInput, Field, Fields,
+    GenericParam, Generics, Ident, Lit, Meta, MetaNameValue, NestedMeta,
+    Variant,
 };
 
 #[derive(Debug)]
@@ -266,7 +267,6 @@ fn all_variants_of(variants: &Punctuated<Variant, Comma>) -> Vec<MatchArm> {
 
 fn generate_rusty_enum_code(
     name: &Ident,
-    generics: Generics,
     variants: &Punctuated<Variant, Comma>,
 ) -> Tokens {
     // We cannot do this:
@@ -284,33 +284,28 @@ fn generate_rusty_enum_code(
         )* 0usize
     );
 
-    impl_enum_like(
-        name,
-        generics,
-        quote! {
-            const NUM_VARIANTS: usize = #enum_count;
-            fn from_discr(value: usize) -> Self {
-                match value {
-                    #(
-                        #match_arm_from
-                    )*
-                    _ => unreachable!()
-                }
+    quote! {
+        const NUM_VARIANTS: usize = #enum_count;
+        fn from_discr(value: usize) -> Self {
+            match value {
+                #(
+                    #match_arm_from
+                )*
+                _ => unreachable!()
             }
-            fn to_discr(self) -> usize {
-                match self {
-                    #(
-                        #match_arm_to
-                    )*
-                }
+        }
+        fn to_discr(self) -> usize {
+            match self {
+                #(
+                    #match_arm_to
+                )*
             }
-        },
-    )
+        }
+    }
 }
 
 fn generate_c_enum_code(
     name: &Ident,
-    generics: Generics,
     variants: &Punctuated<Variant, Comma>,
 ) -> Tokens {
     let variant_a = variants.iter().map(|variant| &variant.ident);
@@ -325,33 +320,28 @@ fn generate_c_enum_code(
 
     let enum_count = variants.len();
 
-    impl_enum_like(
-        name,
-        generics,
-        quote! {
-            const NUM_VARIANTS: usize = #enum_count;
-            fn from_discr(value: usize) -> Self {
-                match value {
-                    #(
-                        #counter_a => #repeat_name_a::#variant_a,
-                    )*
-                    _ => unreachable!()
-                }
+    quote! {
+        const NUM_VARIANTS: usize = #enum_count;
+        fn from_discr(value: usize) -> Self {
+            match value {
+                #(
+                    #counter_a => #repeat_name_a::#variant_a,
+                )*
+                _ => unreachable!()
             }
-            fn to_discr(self) -> usize {
-                match self {
-                    #(
-                        #repeat_name_b::#variant_b => #counter_b,
-                    )*
-                }
+        }
+        fn to_discr(self) -> usize {
+            match self {
+                #(
+                    #repeat_name_b::#variant_b => #counter_b,
+                )*
             }
-        },
-    )
+        }
+    }
 }
 
 fn generate_enum_code(
     name: &Ident,
-    generics: Generics,
     variants: &Punctuated<Variant, Comma>,
 ) -> Tokens {
     // We special-case c-like enums because the generated code is much cleaner
@@ -359,9 +349,9 @@ fn generate_enum_code(
 
     // An empty enum {} is c_like
     if c_like {
-        generate_c_enum_code(name, generics, variants)
+        generate_c_enum_code(name, variants)
     } else {
-        generate_rusty_enum_code(name, generics, variants)
+        generate_rusty_enum_code(name, variants)
     }
 }
 
@@ -370,30 +360,23 @@ fn generate_enum_code(
 // struct S();  (false)
 fn generate_unit_struct_impl(
     name: &Ident,
-    generics: Generics,
     unit: bool,
 ) -> Tokens {
     // Luckly Self {} is valid syntax for Self = S();
     let hack = if unit { quote!() } else { quote!({}) };
 
-    impl_enum_like(
-        name,
-        generics,
-        quote! {
-            const NUM_VARIANTS: usize = 1usize;
-            fn from_discr(_value: usize) -> Self {
-                #name #hack
-            }
-            fn to_discr(self) -> usize {
-                0usize
-            }
-        },
-    )
+    quote! {
+        const NUM_VARIANTS: usize = 1usize;
+        fn from_discr(_value: usize) -> Self {
+            #name #hack
+        }
+        fn to_discr(self) -> usize {
+            0usize
+        }
+    }
 }
 
 fn generate_struct_many_elem(
-    name: &Ident,
-    generics: Generics,
     field_names: &[Tokens],
     type_names: &[Tokens],
 ) -> Tokens {
@@ -403,21 +386,17 @@ fn generate_struct_many_elem(
     let to_discr_0 = to_discr_body(field_names, type_names, offset, None);
     let total_num_variants = total_num_variants_product(type_names);
 
-    impl_enum_like(
-        name,
-        generics,
-        quote! {
-            const NUM_VARIANTS: usize = #total_num_variants;
-            fn from_discr(value: usize) -> Self {
-                Self {
-                    #from_discr_0
-                }
-            }
-            fn to_discr(self) -> usize {
-                #to_discr_0
+    quote! {
+        const NUM_VARIANTS: usize = #total_num_variants;
+        fn from_discr(value: usize) -> Self {
+            Self {
+                #from_discr_0
             }
-        },
-    )
+        }
+        fn to_discr(self) -> usize {
+            #to_discr_0
+        }
+    }
 }
 
 fn total_num_variants_product(type_names: &[Tokens]) -> Tokens {
@@ -533,12 +512,11 @@ fn to_discr_body(
 
 fn generate_struct_with_fields(
     name: &Ident,
-    generics: Generics,
     fields: &Punctuated<Field, Comma>,
 ) -> Tokens {
     let elements = fields.len();
     match elements {
-        0 => generate_unit_struct_impl(name, generics, false),
+        0 => generate_unit_struct_impl(name, false),
         _ => {
             let type_names: Vec<Tokens> =
                 fields.iter().map(|f| f.ty.clone().into_tokens()).collect();
@@ -554,25 +532,24 @@ fn generate_struct_with_fields(
                 }
             }
 
-            generate_struct_many_elem(name, generics, &field_names, &type_names)
+            generate_struct_many_elem(&field_names, &type_names)
         }
     }
 }
 
 fn generate_struct_code(
     name: &Ident,
-    generics: Generics,
     fields: &Fields,
 ) -> Tokens {
     match *fields {
         // Unit struct, just one variant
         // struct S; (it's not the same as struct S {} or struct S())
-        Fields::Unit => generate_unit_struct_impl(name, generics, true),
+        Fields::Unit => generate_unit_struct_impl(name, true),
         Fields::Named(ref f) => {
-            generate_struct_with_fields(name, generics, &f.named)
+            generate_struct_with_fields(name, &f.named)
         }
         Fields::Unnamed(ref f) => {
-            generate_struct_with_fields(name, generics, &f.unnamed)
+            generate_struct_with_fields(name, &f.unnamed)
         }
     }
 }
@@ -601,21 +578,212 @@ fn impl_enum_like(name: &Ident, generics: Generics, body: Tokens) -> Tokens {
     }
 }
 
+// Options set with #[enum_like(...)] attributes
+#[derive(Debug, Default)]
+struct Options {
+    // #[enum_like(reserve_bits = 4)]
+    reserve_bits: Option<u64>,
+    // #[enum_like(symbol = 'A')], only on enum variants
+    symbol: Option<char>,
+    // #[enum_like(static_table)]
+    static_table: bool,
+}
+
+impl Options {
+    fn from_attrs(attrs: &[Attribute]) -> Self {
+        let mut options = Options::default();
+        for attr in attrs {
+            let list = match attr.interpret_meta() {
+                Some(Meta::List(ref list)) if list.ident == "enum_like" => {
+                    list.nested.clone()
+                }
+                _ => continue,
+            };
+            for nested in list {
+                match nested {
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                        ident,
+                        lit: Lit::Int(ref x),
+                        ..
+                    })) if ident == "reserve_bits" => {
+                        if x.value() == 0 {
+                            panic!("#[enum_like(reserve_bits)] must be at least 1");
+                        }
+                        options.reserve_bits = Some(x.value());
+                    }
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                        ident,
+                        lit: Lit::Char(ref c),
+                        ..
+                    })) if ident == "symbol" => {
+                        options.symbol = Some(c.value());
+                    }
+                    NestedMeta::Meta(Meta::Word(ident))
+                        if ident == "static_table" =>
+                    {
+                        options.static_table = true;
+                    }
+                    other => panic!(
+                        "unknown option #[enum_like({})]",
+                        other.into_tokens()
+                    ),
+                }
+            }
+        }
+
+        options
+    }
+
+    // Extra items of the EnumLike impl
+    fn print_consts(&self) -> Tokens {
+        match self.reserve_bits {
+            Some(bits) => {
+                let bits = bits as usize;
+                quote! {
+                    const RESERVED_BITS: usize = #bits;
+                }
+            }
+            None => quote!(),
+        }
+    }
+}
+
+// Implements EnumSymbol when the variants have #[enum_like(symbol = 'A')]
+fn generate_symbol_impl(
+    name: &Ident,
+    generics: Generics,
+    variants: &Punctuated<Variant, Comma>,
+) -> Tokens {
+    let symbols: Vec<Option<char>> = variants
+        .iter()
+        .map(|v| {
+            let options = Options::from_attrs(&v.attrs);
+            if options.reserve_bits.is_some() {
+                panic!("#[enum_like(reserve_bits)] is not valid on variants");
+            }
+            if options.static_table {
+                panic!("#[enum_like(static_table)] is not valid on variants");
+            }
+            options.symbol
+        })
+        .collect();
+    if symbols.iter().all(Option::is_none) {
+        return quote!();
+    }
+    // Every variant needs a different symbol
+    let symbols: Vec<char> = variants
+        .iter()
+        .zip(symbols)
+        .map(|(v, symbol)| {
+            if v.fields != Fields::Unit {
+                panic!("#[enum_like(symbol)] is only valid on unit variants");
+            }
+            symbol.unwrap_or_else(|| {
+                panic!("missing #[enum_like(symbol = ...)] on {}", v.ident)
+            })
+        })
+        .collect();
+    for (i, x) in symbols.iter().enumerate() {
+        if symbols[..i].contains(x) {
+            panic!("duplicate symbol {:?}", x);
+        }
+    }
+
+    let variant = variants.iter().map(|variant| &variant.ident);
+    let repeat_name = iter::repeat(name);
+    let generics = add_trait_bounds(generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics ::enum_like::EnumSymbol for #name #ty_generics
+        #where_clause {
+            fn symbol(self) -> char {
+                match self {
+                    #(
+                        #repeat_name::#variant => #symbols,
+                    )*
+                }
+            }
+        }
+    }
+}
+
+// Implements EnumTable for #[enum_like(static_table)], listing the variants
+// in discriminant order. This needs every value to be a constant, so only
+// enums with unit variants are supported.
+fn generate_table_impl(name: &Ident, generics: &Generics, data: &Data) -> Tokens {
+    let variants = match *data {
+        Data::Enum(DataEnum { ref variants, .. })
+            if variants.iter().all(|v| v.fields == Fields::Unit) =>
+        {
+            variants
+        }
+        _ => panic!(
+            "#[enum_like(static_table)] is only valid on enums with unit variants"
+        ),
+    };
+    if !generics.params.is_empty() {
+        panic!("#[enum_like(static_table)] is not valid on generic enums");
+    }
+    let variant = variants.iter().map(|variant| &variant.ident);
+    let repeat_name = iter::repeat(name);
+
+    quote! {
+        impl ::enum_like::EnumTable for #name {
+            const ALL: &'static [Self] = &[
+                #(
+                    #repeat_name::#variant,
+                )*
+            ];
+        }
+    }
+}
+
 /// Function that implements the `#[derive(EnumLike)]` proc macro
-#[proc_macro_derive(EnumLike)]
+#[proc_macro_derive(EnumLike, attributes(enum_like))]
 pub fn derive_enum_like(input: TokenStream) -> TokenStream {
     let input: DeriveInput = syn::parse(input).unwrap();
-    match input.data {
+    let options = Options::from_attrs(&input.attrs);
+    if options.symbol.is_some() {
+        panic!("#[enum_like(symbol)] is only valid on enum variants");
+    }
+    let symbol_impl = match input.data {
+        Data::Enum(DataEnum { ref variants, .. }) => {
+            generate_symbol_impl(&input.ident, input.generics.clone(), variants)
+        }
+        _ => quote!(),
+    };
+    let table_impl = if options.static_table {
+        generate_table_impl(&input.ident, &input.generics, &input.data)
+    } else {
+        quote!()
+    };
+    let body = match input.data {
         Data::Enum(DataEnum { ref variants, .. }) => {
-            generate_enum_code(&input.ident, input.generics, variants)
+            generate_enum_code(&input.ident, variants)
         }
         Data::Struct(DataStruct { ref fields, .. }) => {
-            generate_struct_code(&input.ident, input.generics, fields)
+            generate_struct_code(&input.ident, fields)
         }
         Data::Union(..) => {
             panic!("#[derive(EnumLike)] is only defined for enums and structs")
         }
-    }.into()
+    };
+    let consts = options.print_consts();
+    let enum_like_impl = impl_enum_like(
+        &input.ident,
+        input.generics,
+        quote! {
+            #consts
+            #body
+        },
+    );
+
+    quote!(
+        #enum_like_impl
+        #symbol_impl
+        #table_impl
+    ).into()
 }
 
 // The tests are in the ../example crate
@@ -13,14 +13,18 @@
 //! For usage examples, check out
 //! <https://github.com/Badel2/enum_vec/blob/master/example/src/enum_set.rs>
 extern crate enum_like;
+extern crate enum_vec;
 extern crate bit_set;
 
 use enum_like::EnumLike;
+use enum_vec::EnumVec;
 use bit_set::BitSet;
 use std::marker::PhantomData;
 use std::iter::FromIterator;
 use std::fmt;
 
+pub use enum_vec::Base64Error;
+
 /// A `BitSet` indexed by an `EnumLike` type.
 #[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct EnumSet<E: EnumLike> {
@@ -161,6 +165,43 @@ impl<E: EnumLike> EnumSet<E> {
         let d = value.to_discr();
         self.inner.remove(d)
     }
+
+    /// Encodes the set as a short URL-safe string. This is the code of an
+    /// `EnumVec<bool>`, see `EnumVec::to_base64url`, which stops at the
+    /// largest element of the set.
+    /// ```
+    /// extern crate enum_set2;
+    /// use enum_set2::EnumSet;
+    ///
+    /// let mut set = EnumSet::new();
+    /// set.insert(Some(false));
+    /// let code = set.to_base64url();
+    /// assert_eq!(EnumSet::from_base64url(&code), Ok(set));
+    /// ```
+    pub fn to_base64url(&self) -> String {
+        let len = self.inner.iter().last().map_or(0, |x| x + 1);
+        let v: EnumVec<bool> = (0..len).map(|i| self.inner.contains(i)).collect();
+
+        v.to_base64url()
+    }
+
+    /// Decodes a string created by `to_base64url`. Returns an error if the
+    /// checksum does not match, or if it contains a value which is not a
+    /// valid discriminant of `E`.
+    pub fn from_base64url(s: &str) -> Result<Self, Base64Error> {
+        let v = EnumVec::<bool>::from_base64url(s)?;
+        let mut inner = BitSet::with_capacity(v.len());
+        for (i, x) in v.iter().enumerate() {
+            if x {
+                if i >= E::NUM_VARIANTS {
+                    return Err(Base64Error::InvalidDiscriminant(i));
+                }
+                inner.insert(i);
+            }
+        }
+
+        Ok(Self::from_bit_set(inner))
+    }
 }
 
 impl<E: EnumLike> Default for EnumSet<E> {
@@ -301,5 +342,23 @@ mod tests {
         assert_eq!(e.contains(ABC::A), false);
     }
 
+    #[test]
+    fn base64url() {
+        let mut e = EnumSet::new();
+        assert_eq!(EnumSet::from_base64url(&e.to_base64url()), Ok(e.clone()));
+        e.insert(ABC::C);
+        e.insert(ABC::A);
+        let code = e.to_base64url();
+        assert_eq!(EnumSet::from_base64url(&code), Ok(e));
+        // A set of Option<ABC> containing None, with discriminant 3
+        let mut o = EnumSet::new();
+        o.insert(None::<ABC>);
+        let code = o.to_base64url();
+        assert_eq!(
+            EnumSet::<ABC>::from_base64url(&code),
+            Err(Base64Error::InvalidDiscriminant(3))
+        );
+    }
+
     // As for now, this crate is assumed to work because of its simplicity.
 }
@@ -0,0 +1,316 @@
+use enum_like::EnumLike;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use vec_u64::packing;
+use vec_u64::EnumVec;
+
+/// A fixed-size vector of enum variants which can be updated concurrently
+/// from many threads, without locks.
+///
+/// The layout is the same as `vec_u64::EnumVec`, using `AtomicU64` blocks.
+/// Each operation is implemented with atomic operations on the block which
+/// contains the element, so operations on different elements of the same
+/// block do not interfere with each other: they may only need to retry the
+/// compare-and-swap. The `Ordering` arguments have the same meaning as in
+/// the methods of `AtomicU64` with the same name.
+///
+/// ```
+/// use enum_vec::atomic_u64::AtomicEnumVec;
+/// use std::sync::atomic::Ordering;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let v = Arc::new(AtomicEnumVec::from_elem(None, 64));
+/// let handles: Vec<_> = (0..4)
+///     .map(|t| {
+///         let v = Arc::clone(&v);
+///         thread::spawn(move || {
+///             for i in (t..64).step_by(4) {
+///                 v.store(i, Some(t % 2 == 0), Ordering::Relaxed);
+///             }
+///         })
+///     })
+///     .collect();
+/// for h in handles {
+///     h.join().unwrap();
+/// }
+/// let ev = v.to_enum_vec(Ordering::SeqCst);
+/// assert_eq!(ev.get(2), Some(Some(true)));
+/// assert_eq!(ev.count(None), 0);
+/// ```
+pub struct AtomicEnumVec<T: EnumLike> {
+    storage: Box<[AtomicU64]>,
+    num_elements: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: EnumLike> AtomicEnumVec<T> {
+    const BITS: usize = EnumVec::<T>::BITS_PER_ELEM;
+
+    /// Creates a vector with `n` copies of `x`.
+    pub fn from_elem(x: T, n: usize) -> Self {
+        Self::from(EnumVec::from_elem(x, n))
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.num_elements
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    // Returns the block which contains element i, and the bit offset
+    fn block(&self, i: usize) -> (&AtomicU64, usize) {
+        if i >= self.len() {
+            panic!("index out of bounds: {} >= {}", i, self.len());
+        }
+        let (idx_w, idx_b) = packing::block_index(Self::BITS, i);
+
+        (&self.storage[idx_w], idx_b)
+    }
+
+    fn extract(block: u64, shift: usize) -> usize {
+        ((block >> shift) & packing::element_mask(Self::BITS)) as usize
+    }
+
+    fn replace(block: u64, shift: usize, x: T) -> u64 {
+        let mask = packing::element_mask(Self::BITS) << shift;
+        (block & !mask) | ((x.to_discr() as u64) << shift)
+    }
+
+    /// Loads the element at position `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`, or if `order` is `Release` or `AcqRel`.
+    pub fn load(&self, i: usize, order: Ordering) -> T {
+        let (block, shift) = self.block(i);
+
+        T::from_discr(Self::extract(block.load(order), shift))
+    }
+
+    /// Stores `x` at position `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`.
+    pub fn store(&self, i: usize, x: T, order: Ordering) {
+        self.swap(i, x, order);
+    }
+
+    /// Stores `x` at position `i`, returning the previous element.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`.
+    pub fn swap(&self, i: usize, x: T, order: Ordering) -> T {
+        match self.fetch_update(i, order, failure_ordering(order), |_| Some(x)) {
+            Ok(old) | Err(old) => old,
+        }
+    }
+
+    /// Stores `new` at position `i` if the current element is equal to
+    /// `current`. On success returns `Ok(current)`, otherwise returns the
+    /// current element as `Err`.
+    ///
+    /// Unlike `AtomicU64::compare_exchange`, this never fails because of
+    /// concurrent updates to the other elements of the same block.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`, or if the orderings are invalid for
+    /// `AtomicU64::compare_exchange`.
+    /// ```
+    /// use enum_vec::atomic_u64::AtomicEnumVec;
+    /// use std::sync::atomic::Ordering::SeqCst;
+    ///
+    /// let v = AtomicEnumVec::from_elem(false, 10);
+    /// assert_eq!(v.compare_exchange(3, false, true, SeqCst, SeqCst), Ok(false));
+    /// assert_eq!(v.compare_exchange(3, false, true, SeqCst, SeqCst), Err(true));
+    /// ```
+    pub fn compare_exchange(
+        &self,
+        i: usize,
+        current: T,
+        new: T,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<T, T> {
+        let current = current.to_discr();
+        self.fetch_update(i, success, failure, |x| {
+            if x.to_discr() == current {
+                Some(new)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Fetches the element at position `i`, and applies `f` to it. If `f`
+    /// returns `Some(new)`, stores `new` and returns `Ok(previous)`,
+    /// otherwise returns `Err(previous)`.
+    ///
+    /// `f` may be called many times if there are concurrent updates to the
+    /// same block.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`, or if the orderings are invalid for
+    /// `AtomicU64::fetch_update`.
+    pub fn fetch_update<F>(
+        &self,
+        i: usize,
+        set_order: Ordering,
+        fetch_order: Ordering,
+        mut f: F,
+    ) -> Result<T, T>
+    where
+        F: FnMut(T) -> Option<T>,
+    {
+        let (block, shift) = self.block(i);
+        let mut prev = block.load(fetch_order);
+        loop {
+            let x = T::from_discr(Self::extract(prev, shift));
+            let new = match f(x) {
+                Some(new) => Self::replace(prev, shift, new),
+                None => return Err(x),
+            };
+            match block.compare_exchange_weak(prev, new, set_order, fetch_order) {
+                Ok(_) => return Ok(x),
+                Err(actual) => prev = actual,
+            }
+        }
+    }
+
+    /// Loads every element into an `EnumVec`. The elements are loaded one
+    /// block at a time, so this is not an atomic snapshot of the whole
+    /// vector.
+    pub fn to_enum_vec(&self, order: Ordering) -> EnumVec<T> {
+        let storage = self.storage.iter().map(|b| b.load(order)).collect();
+
+        // The blocks were created from an EnumVec<T>, and only updated with
+        // valid discriminants
+        unsafe { EnumVec::from_storage(storage, self.num_elements) }
+    }
+
+    /// Consumes the vector and returns the inner `EnumVec`. This is not an
+    /// atomic operation, but the ownership guarantees that there are no
+    /// concurrent accesses.
+    pub fn into_inner(self) -> EnumVec<T> {
+        let storage = self
+            .storage
+            .into_vec()
+            .into_iter()
+            .map(AtomicU64::into_inner)
+            .collect();
+
+        unsafe { EnumVec::from_storage(storage, self.num_elements) }
+    }
+}
+
+// The strongest failure ordering allowed for a given success ordering
+fn failure_ordering(order: Ordering) -> Ordering {
+    match order {
+        Ordering::Release => Ordering::Relaxed,
+        Ordering::AcqRel => Ordering::Acquire,
+        x => x,
+    }
+}
+
+impl<T: EnumLike> From<EnumVec<T>> for AtomicEnumVec<T> {
+    fn from(v: EnumVec<T>) -> Self {
+        let blocks = packing::blocks_for_elements(Self::BITS, v.len());
+        let storage = v.storage()[..blocks]
+            .iter()
+            .map(|&b| AtomicU64::new(b))
+            .collect();
+
+        Self {
+            storage,
+            num_elements: v.len(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: EnumLike> From<AtomicEnumVec<T>> for EnumVec<T> {
+    fn from(v: AtomicEnumVec<T>) -> Self {
+        v.into_inner()
+    }
+}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for AtomicEnumVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_enum_vec(Ordering::SeqCst), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering::{Relaxed, SeqCst};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn single_thread() {
+        let ev: EnumVec<_> = (0..100u32).map(|i| i % 3 == 0).collect();
+        let v = AtomicEnumVec::from(ev.clone());
+        assert_eq!(v.len(), 100);
+        assert!(v.load(3, SeqCst));
+        assert!(v.swap(3, false, SeqCst));
+        assert!(!v.load(3, SeqCst));
+        assert_eq!(v.fetch_update(4, SeqCst, SeqCst, |x| Some(!x)), Ok(false));
+        assert_eq!(v.fetch_update(4, SeqCst, SeqCst, |_| None), Err(true));
+        let mut expected = ev;
+        expected.set(3, false);
+        expected.set(4, true);
+        assert_eq!(v.to_enum_vec(SeqCst), expected);
+        assert_eq!(v.into_inner(), expected);
+    }
+
+    #[test]
+    fn concurrent_updates_same_block() {
+        // 16 elements of 2 bits, all in the same block
+        let v = Arc::new(AtomicEnumVec::from_elem([false, false], 16));
+        let handles: Vec<_> = (0..16)
+            .map(|t| {
+                let v = Arc::clone(&v);
+                thread::spawn(move || {
+                    for _ in 0..3 {
+                        v.fetch_update(t, Relaxed, Relaxed, |x| {
+                            Some(<[bool; 2]>::from_discr(x.to_discr() + 1))
+                        }).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert!(v.to_enum_vec(SeqCst).all([true, true]));
+    }
+
+    #[test]
+    fn compare_exchange_has_one_winner() {
+        let v = Arc::new(AtomicEnumVec::from_elem(None, 8));
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let v = Arc::clone(&v);
+                thread::spawn(move || {
+                    // Other threads also update element 1, in the same block
+                    v.store(1, Some(t % 2 == 0), SeqCst);
+                    v.compare_exchange(0, None, Some(true), SeqCst, SeqCst)
+                        .is_ok() as usize
+                })
+            })
+            .collect();
+        let winners: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(winners, 1);
+        assert_eq!(v.load(0, SeqCst), Some(true));
+    }
+}
@@ -0,0 +1,277 @@
+//! Short URL-safe codes for an `EnumVec`.
+//!
+//! A code is the base64url encoding, without padding, of these bytes:
+//!
+//! * The number of elements, as an LEB128 varint.
+//! * The storage blocks in little-endian, as in the packed format, but
+//!   without the bytes after the last element.
+//! * A 16-bit checksum of the previous bytes and of the element type, in
+//!   little-endian.
+//!
+//! So a vector of 10 `bool` uses 1 + 2 + 2 bytes, and its code has 7
+//! characters.
+
+use enum_like::EnumLike;
+use packed::first_invalid;
+use std::error::Error;
+use std::fmt;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+const ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+// Marks the bytes which are not in ALPHABET
+const INVALID: u8 = 0xFF;
+const CHECKSUM_BYTES: usize = 2;
+
+/// Error while decoding a base64url code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Base64Error {
+    /// The character at this index is not valid base64url
+    InvalidCharacter(usize),
+    /// The code is too short or too long for the number of elements
+    InvalidLength,
+    /// The checksum does not match: the code was modified, or it was created
+    /// for a different element type
+    BadChecksum,
+    /// The element at this index is not a valid discriminant
+    InvalidDiscriminant(usize),
+}
+
+impl fmt::Display for Base64Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Base64Error::InvalidCharacter(i) => {
+                write!(f, "invalid character at index {}", i)
+            }
+            Base64Error::InvalidLength => write!(f, "invalid length"),
+            Base64Error::BadChecksum => write!(f, "checksum mismatch"),
+            Base64Error::InvalidDiscriminant(i) => {
+                write!(f, "invalid discriminant at index {}", i)
+            }
+        }
+    }
+}
+
+impl Error for Base64Error {}
+
+fn encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let mut b = [0; 3];
+        b[..chunk.len()].copy_from_slice(chunk);
+        let x = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        // 2, 3 or 4 characters for 1, 2 or 3 bytes
+        for k in 0..=chunk.len() {
+            s.push(ALPHABET[(x >> (18 - 6 * k)) as usize & 0x3F] as char);
+        }
+    }
+
+    s
+}
+
+fn decode(s: &str) -> Result<Vec<u8>, Base64Error> {
+    let mut table = [INVALID; 256];
+    for (i, &c) in ALPHABET.iter().enumerate() {
+        table[c as usize] = i as u8;
+    }
+    if s.len() % 4 == 1 {
+        return Err(Base64Error::InvalidLength);
+    }
+
+    let mut bytes = Vec::with_capacity(s.len() / 4 * 3 + 2);
+    for (c, chunk) in s.as_bytes().chunks(4).enumerate() {
+        let mut x = 0;
+        for (k, &b) in chunk.iter().enumerate() {
+            let v = table[b as usize];
+            if v == INVALID {
+                return Err(Base64Error::InvalidCharacter(c * 4 + k));
+            }
+            x |= (v as u32) << (18 - 6 * k);
+        }
+        // The unused bits of the last character are ignored
+        bytes.extend_from_slice(&x.to_be_bytes()[1..chunk.len()]);
+    }
+
+    Ok(bytes)
+}
+
+// FNV-1a of the element type and the bytes, folded to 16 bits
+fn checksum<T: EnumLike>(bytes: &[u8]) -> [u8; CHECKSUM_BYTES] {
+    let mut h: u32 = 0x811c_9dc5;
+    let bits = EnumVec::<T>::BITS_PER_ELEM as u8;
+    let num_variants = (T::NUM_VARIANTS as u64).to_le_bytes();
+    for &b in [bits].iter().chain(&num_variants).chain(bytes) {
+        h = (h ^ b as u32).wrapping_mul(0x0100_0193);
+    }
+
+    (((h >> 16) ^ h) as u16).to_le_bytes()
+}
+
+// Number of bytes used by the blocks of len elements, without the unused
+// bytes of the last block
+pub(crate) fn data_bytes(bits: usize, len: usize) -> Option<usize> {
+    let (b, shift) = packing::block_index(bits, len);
+
+    b.checked_mul(4)?.checked_add((shift + 7) / 8)
+}
+
+impl<T: EnumLike> EnumVec<T> {
+    /// Encodes the vector as a short URL-safe string, see the `base64url`
+    /// module.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![Some(true), None, Some(false)].into();
+    /// let code = ev.to_base64url();
+    /// assert_eq!(code.len(), 6);
+    /// assert_eq!(EnumVec::from_base64url(&code), Ok(ev));
+    /// ```
+    pub fn to_base64url(&self) -> String {
+        let mut len = self.len();
+        let mut bytes = vec![];
+        loop {
+            let b = (len & 0x7F) as u8;
+            len >>= 7;
+            if len == 0 {
+                bytes.push(b);
+                break;
+            }
+            bytes.push(b | 0x80);
+        }
+        let blocks = packing::blocks_for_elements(Self::BITS_PER_ELEM, self.len());
+        for b in 0..blocks {
+            bytes.extend_from_slice(&self.masked_block(b).to_le_bytes());
+        }
+        let end = bytes.len() - blocks * 4
+            + data_bytes(Self::BITS_PER_ELEM, self.len()).unwrap();
+        bytes.truncate(end);
+        let checksum = checksum::<T>(&bytes);
+        bytes.extend_from_slice(&checksum);
+
+        encode(&bytes)
+    }
+
+    /// Decodes a string created by `to_base64url`, checking the checksum and
+    /// that every element is a valid discriminant.
+    pub fn from_base64url(s: &str) -> Result<Self, Base64Error> {
+        let bytes = decode(s)?;
+        let mut len: u64 = 0;
+        let mut data = None;
+        for (i, &b) in bytes.iter().enumerate().take(10) {
+            // The 10th byte only has the highest bit of the u64
+            if i == 9 && b > 1 {
+                return Err(Base64Error::InvalidLength);
+            }
+            len |= ((b & 0x7F) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                data = Some(i + 1);
+                break;
+            }
+        }
+        let data = data.ok_or(Base64Error::InvalidLength)?;
+        if len > usize::MAX as u64 {
+            return Err(Base64Error::InvalidLength);
+        }
+        let len = len as usize;
+        let n = data_bytes(Self::BITS_PER_ELEM, len)
+            .ok_or(Base64Error::InvalidLength)?;
+        if bytes.len() - data != n + CHECKSUM_BYTES {
+            return Err(Base64Error::InvalidLength);
+        }
+        let (content, expected) = bytes.split_at(data + n);
+        if checksum::<T>(content) != expected {
+            return Err(Base64Error::BadChecksum);
+        }
+
+        let storage: Vec<u32> = content[data..]
+            .chunks(4)
+            .map(|c| {
+                let mut b = [0; 4];
+                b[..c.len()].copy_from_slice(c);
+                u32::from_le_bytes(b)
+            })
+            .collect();
+        let epb = Self::ELEMS_PER_BLOCK;
+        for (b, &block) in storage.iter().enumerate() {
+            let n = ::std::cmp::min(epb, len - b * epb);
+            if let Some(k) = first_invalid::<T>(block, n) {
+                return Err(Base64Error::InvalidDiscriminant(b * epb + k));
+            }
+        }
+
+        // All the discriminants were checked
+        Ok(unsafe { Self::from_storage(storage, len) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec() {
+        for n in 0..20usize {
+            let bytes: Vec<u8> = (0..n).map(|i| (i * 97 + 13) as u8).collect();
+            let s = encode(&bytes);
+            assert_eq!(s.len(), (n * 8 + 5) / 6);
+            assert_eq!(decode(&s).unwrap(), bytes);
+        }
+        assert_eq!(encode(b"\xfb\xff"), "-_8");
+        assert_eq!(decode("ab.d"), Err(Base64Error::InvalidCharacter(2)));
+        assert_eq!(decode("abcde"), Err(Base64Error::InvalidLength));
+    }
+
+    #[test]
+    fn round_trip() {
+        for &n in &[0, 1, 9, 10, 11, 200, 1000] {
+            let ev: EnumVec<_> = (0..n)
+                .map(|i| <Option<(bool, bool)>>::from_discr(i * 7 % 5))
+                .collect();
+            let code = ev.to_base64url();
+            assert_eq!(EnumVec::from_base64url(&code), Ok(ev));
+        }
+        let ev: EnumVec<_> = vec![true; 10].into();
+        assert_eq!(ev.to_base64url().len(), 7);
+    }
+
+    #[test]
+    fn invalid_codes() {
+        let ev: EnumVec<_> = vec![None, Some(true), Some(false)].into();
+        let code = ev.to_base64url();
+        let mut bad = code.clone().into_bytes();
+        bad[2] = if bad[2] == b'A' { b'B' } else { b'A' };
+        let bad = String::from_utf8(bad).unwrap();
+        assert_eq!(
+            EnumVec::<Option<bool>>::from_base64url(&bad),
+            Err(Base64Error::BadChecksum)
+        );
+        // Different type, same number of bits
+        assert_eq!(
+            EnumVec::<[bool; 2]>::from_base64url(&code),
+            Err(Base64Error::BadChecksum)
+        );
+        assert_eq!(
+            EnumVec::<Option<bool>>::from_base64url(&code[..4]),
+            Err(Base64Error::InvalidLength)
+        );
+
+        // Element 1 set to the invalid discriminant 3, with a valid checksum
+        let mut bytes = vec![3, 0b11_11_10];
+        let sum = checksum::<Option<bool>>(&bytes);
+        bytes.extend_from_slice(&sum);
+        assert_eq!(
+            EnumVec::<Option<bool>>::from_base64url(&encode(&bytes)),
+            Err(Base64Error::InvalidDiscriminant(1))
+        );
+
+        // Length 3 encoded with 10 bytes, the last one may only be 0 or 1
+        for &(last, ok) in &[(0, true), (2, false)] {
+            let mut bytes = vec![0x83, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, last, 0];
+            let sum = checksum::<Option<bool>>(&bytes);
+            bytes.extend_from_slice(&sum);
+            let ev = EnumVec::<Option<bool>>::from_base64url(&encode(&bytes));
+            assert_eq!(ev.map(|v| v.len()), if ok { Ok(3) } else { Err(Base64Error::InvalidLength) });
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use enum_like::EnumLike;
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ops::Range;
+use vec_u32::{BitVec, EnumVec};
+
+// The storage block of BitVec
+type Block = u32;
+const BLOCK_SIZE: usize = 32;
+
+/// A vector of enum variants stored as bit planes.
+///
+/// Instead of packing the bits of each element together, like `EnumVec`
+/// does, bit `k` of every element is stored in plane `k`, a `BitVec`. This
+/// makes single element access slower, as it must read one bit from each
+/// plane, but queries like `count(x)`, `any(x)`, or "which elements are in
+/// this set" become boolean algebra over whole words of the planes.
+///
+/// ```
+/// use enum_vec::BitSlicedEnumVec;
+///
+/// let mut bs = BitSlicedEnumVec::new();
+/// bs.push(Some(true));
+/// bs.push(None);
+/// bs.push(Some(false));
+/// bs.push(None);
+/// assert_eq!(bs.get(1), Some(None));
+/// assert_eq!(bs.count(None), 2);
+/// let m = bs.mask_in(&[Some(true), Some(false)]);
+/// assert_eq!(m.to_vec(), vec![true, false, true, false]);
+/// ```
+#[derive(Clone)]
+pub struct BitSlicedEnumVec<T: EnumLike> {
+    // planes[k] has the bit k of every element
+    planes: Vec<BitVec>,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: EnumLike> BitSlicedEnumVec<T> {
+    /// Creates an empty vector.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty vector with space for at least `n` elements.
+    pub fn with_capacity(n: usize) -> Self {
+        let num_planes = EnumVec::<T>::BITS_PER_ELEM;
+        Self {
+            planes: (0..num_planes).map(|_| BitVec::with_capacity(n)).collect(),
+            len: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bit planes, `planes()[k]` has the bit `k` of the
+    /// discriminant of every element.
+    pub fn planes(&self) -> &[BitVec] {
+        &self.planes
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+        let mut x = 0;
+        for (k, plane) in self.planes.iter().enumerate() {
+            x |= (plane.get(i).unwrap() as usize) << k;
+        }
+
+        Some(T::from_discr(x))
+    }
+
+    /// Sets the element at position `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`.
+    pub fn set(&mut self, i: usize, x: T) {
+        if i >= self.len {
+            panic!("index out of bounds: {} >= {}", i, self.len);
+        }
+        let x = x.to_discr();
+        for (k, plane) in self.planes.iter_mut().enumerate() {
+            plane.set(i, (x >> k) & 1 == 1);
+        }
+    }
+
+    /// Appends an element to the end of the vector.
+    pub fn push(&mut self, x: T) {
+        let x = x.to_discr();
+        for (k, plane) in self.planes.iter_mut().enumerate() {
+            plane.push((x >> k) & 1 == 1);
+        }
+        self.len += 1;
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        let x = self.get(self.len.checked_sub(1)?);
+        for plane in &mut self.planes {
+            plane.pop();
+        }
+        self.len -= 1;
+
+        x
+    }
+
+    /// Shortens the vector to `len` elements.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            for plane in &mut self.planes {
+                plane.truncate(len);
+            }
+            self.len = len;
+        }
+    }
+
+    /// Removes all the elements.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Inserts an element at position `index`, shifting all the elements
+    /// after it to the right.
+    ///
+    /// # Panics
+    ///
+    /// If `index > self.len()`.
+    pub fn insert(&mut self, index: usize, x: T) {
+        if index > self.len {
+            panic!("index out of bounds: {} > {}", index, self.len);
+        }
+        let x = x.to_discr();
+        for (k, plane) in self.planes.iter_mut().enumerate() {
+            plane.insert(index, (x >> k) & 1 == 1);
+        }
+        self.len += 1;
+    }
+
+    /// Removes and returns the element at position `index`, shifting all
+    /// the elements after it to the left.
+    ///
+    /// # Panics
+    ///
+    /// If `index >= self.len()`.
+    pub fn remove(&mut self, index: usize) -> T {
+        if index >= self.len {
+            panic!("index out of bounds: {} >= {}", index, self.len);
+        }
+        let x = self.get(index).unwrap();
+        for plane in &mut self.planes {
+            plane.remove(index);
+        }
+        self.len -= 1;
+
+        x
+    }
+
+    /// Swaps two elements.
+    ///
+    /// # Panics
+    ///
+    /// If `a` or `b` are out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        let xa = self.get(a).unwrap();
+        let xb = self.get(b).unwrap();
+        self.set(a, xb);
+        self.set(b, xa);
+    }
+
+    /// Resizes the vector to `new_len` elements, filling the new positions
+    /// with `value`.
+    pub fn resize(&mut self, new_len: usize, value: T) {
+        if new_len > self.len {
+            let x = value.to_discr();
+            for (k, plane) in self.planes.iter_mut().enumerate() {
+                plane.resize(new_len, (x >> k) & 1 == 1);
+            }
+            self.len = new_len;
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    /// Appends all the elements of `other`.
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        self.extend(other.iter().cloned());
+    }
+
+    /// Keeps only the elements for which `f` returns `true`, preserving
+    /// their order.
+    /// ```
+    /// use enum_vec::BitSlicedEnumVec;
+    ///
+    /// let mut bs: BitSlicedEnumVec<_> = vec![Some(true), None, Some(false)].into_iter().collect();
+    /// bs.retain(|x| x.is_some());
+    /// assert_eq!(bs.to_vec(), vec![Some(true), Some(false)]);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut kept = 0;
+        for i in 0..self.len {
+            let x = self.get(i).unwrap();
+            if f(&x) {
+                self.set(kept, x);
+                kept += 1;
+            }
+        }
+        self.truncate(kept);
+    }
+
+    /// Iterator over the elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            v: self,
+            range: 0..self.len,
+        }
+    }
+
+    /// Copies `self` into a plain `Vec`.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+
+    /// Converts into an `EnumVec`.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        let mut v = EnumVec::with_capacity(self.len);
+        v.extend(self.iter());
+
+        v
+    }
+
+    fn num_blocks(&self) -> usize {
+        (self.len + BLOCK_SIZE - 1) / BLOCK_SIZE
+    }
+
+    // Mask of the valid bits of block b
+    fn valid_bits(&self, b: usize) -> Block {
+        let used = self.len - b * BLOCK_SIZE;
+        if used >= BLOCK_SIZE {
+            !0
+        } else {
+            !(!0 << used)
+        }
+    }
+
+    // Block b of the mask of elements equal to the discriminant x
+    fn eq_block(&self, x: usize, b: usize) -> Block {
+        let mut acc = self.valid_bits(b);
+        for (k, plane) in self.planes.iter().enumerate() {
+            let block = plane.storage()[b];
+            acc &= if (x >> k) & 1 == 1 { block } else { !block };
+        }
+
+        acc
+    }
+
+    // Build a BitVec from a function which returns each block
+    fn mask_from_blocks<F>(&self, f: F) -> BitVec
+    where
+        F: FnMut(usize) -> Block,
+    {
+        let blocks = (0..self.num_blocks()).map(f).collect();
+        // Every bit pattern is a valid BitVec
+        unsafe { BitVec::from_storage(blocks, self.len) }
+    }
+
+    /// Count the elements equal to `x`.
+    /// ```
+    /// use enum_vec::BitSlicedEnumVec;
+    ///
+    /// let bs: BitSlicedEnumVec<_> = vec![Some(false); 100].into_iter().collect();
+    /// assert_eq!(bs.count(Some(false)), 100);
+    /// assert_eq!(bs.count(None), 0);
+    /// ```
+    pub fn count(&self, x: T) -> usize {
+        let x = x.to_discr();
+        (0..self.num_blocks())
+            .map(|b| self.eq_block(x, b).count_ones() as usize)
+            .sum()
+    }
+
+    /// Check whether any of the elements is equal to `x`.
+    pub fn any(&self, x: T) -> bool {
+        let x = x.to_discr();
+        (0..self.num_blocks()).any(|b| self.eq_block(x, b) != 0)
+    }
+
+    /// Check whether all of the elements are equal to `x`.
+    pub fn all(&self, x: T) -> bool {
+        let x = x.to_discr();
+        (0..self.num_blocks())
+            .all(|b| self.eq_block(x, b) == self.valid_bits(b))
+    }
+
+    /// Returns a `BitVec` with `true` at the positions of the elements equal
+    /// to `x`.
+    pub fn mask_eq(&self, x: T) -> BitVec {
+        let x = x.to_discr();
+        self.mask_from_blocks(|b| self.eq_block(x, b))
+    }
+
+    /// Returns a `BitVec` with `true` at the positions of the elements
+    /// which are in `set`.
+    pub fn mask_in(&self, set: &[T]) -> BitVec {
+        self.mask_from_blocks(|b| {
+            set.iter().fold(0, |acc, x| acc | self.eq_block(x.to_discr(), b))
+        })
+    }
+
+    /// Compares two vectors element by element, returning a `BitVec` with
+    /// `true` at the positions where both elements are equal.
+    ///
+    /// # Panics
+    ///
+    /// If both vectors have different lengths.
+    /// ```
+    /// use enum_vec::BitSlicedEnumVec;
+    ///
+    /// let a: BitSlicedEnumVec<_> = vec![(true, false), (false, false)].into_iter().collect();
+    /// let b: BitSlicedEnumVec<_> = vec![(true, false), (false, true)].into_iter().collect();
+    /// assert_eq!(a.eq_mask(&b).to_vec(), vec![true, false]);
+    /// ```
+    pub fn eq_mask(&self, other: &Self) -> BitVec {
+        assert_eq!(self.len, other.len, "length mismatch");
+        self.mask_from_blocks(|b| {
+            let mut acc = self.valid_bits(b);
+            for (pa, pb) in self.planes.iter().zip(&other.planes) {
+                acc &= !(pa.storage()[b] ^ pb.storage()[b]);
+            }
+            acc
+        })
+    }
+
+    /// Returns a copy of the elements in `range`.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= self.len,
+                "range out of bounds");
+        let mut v = Self::with_capacity(range.end - range.start);
+        for i in range {
+            v.push(self.get(i).unwrap());
+        }
+
+        v
+    }
+}
+
+impl<T: EnumLike> Default for BitSlicedEnumVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for BitSlicedEnumVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: EnumLike> PartialEq for BitSlicedEnumVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.planes == other.planes
+    }
+}
+
+impl<T: EnumLike> Eq for BitSlicedEnumVec<T> {}
+
+impl<T: EnumLike> Extend<T> for BitSlicedEnumVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+impl<T: EnumLike> FromIterator<T> for BitSlicedEnumVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut v = Self::new();
+        v.extend(iter);
+
+        v
+    }
+}
+
+impl<'a, T: EnumLike> From<&'a EnumVec<T>> for BitSlicedEnumVec<T> {
+    fn from(v: &'a EnumVec<T>) -> Self {
+        let mut bs = Self::with_capacity(v.len());
+        bs.extend(v.iter());
+
+        bs
+    }
+}
+
+impl<T: EnumLike> From<BitSlicedEnumVec<T>> for EnumVec<T> {
+    fn from(v: BitSlicedEnumVec<T>) -> Self {
+        v.to_enum_vec()
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for &'a BitSlicedEnumVec<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over &BitSlicedEnumVec
+pub struct Iter<'a, T: 'a + EnumLike> {
+    v: &'a BitSlicedEnumVec<T>,
+    range: Range<usize>,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|i| self.v.get(i).unwrap())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'a, T: EnumLike> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|i| self.v.get(i).unwrap())
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enum_like::EnumValues;
+
+    fn sample() -> Vec<Option<(bool, bool)>> {
+        (0..333u32)
+            .map(|i| <Option<(bool, bool)>>::from_discr((i * 7 % 5) as usize))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let v = sample();
+        let ev = EnumVec::from(v.clone());
+        let bs = BitSlicedEnumVec::from(&ev);
+        assert_eq!(bs.to_vec(), v);
+        assert_eq!(EnumVec::from(bs), ev);
+    }
+
+    #[test]
+    fn queries_match_enum_vec() {
+        let v = sample();
+        let ev = EnumVec::from(v.clone());
+        let mut bs = BitSlicedEnumVec::from(&ev);
+        // Make sure the unused bits of the last block are not counted
+        bs.push(None);
+        bs.pop();
+        for x in <Option<(bool, bool)>>::values() {
+            assert_eq!(bs.count(x), ev.count(x));
+            assert_eq!(bs.any(x), ev.any(x));
+            assert_eq!(bs.all(x), ev.all(x));
+            let m: Vec<_> = v.iter().map(|y| *y == x).collect();
+            assert_eq!(bs.mask_eq(x).to_vec(), m);
+        }
+        let set = [None, Some((true, true))];
+        let m: Vec<_> = v.iter().map(|y| set.contains(y)).collect();
+        assert_eq!(bs.mask_in(&set).to_vec(), m);
+        assert!(BitSlicedEnumVec::<bool>::new().all(true));
+    }
+
+    #[test]
+    fn set_and_compare() {
+        let v = sample();
+        let a: BitSlicedEnumVec<_> = v.iter().cloned().collect();
+        let mut b = a.clone();
+        b.set(10, None);
+        b.set(300, Some((false, true)));
+        let m = a.eq_mask(&b);
+        assert_eq!(m.count_zeros(),
+                   v[10].is_some() as usize + (v[300] != Some((false, true))) as usize);
+        assert_eq!(b.slice(10..12).get(0), Some(None));
+    }
+
+    #[test]
+    fn vec_operations() {
+        let mut v = sample();
+        let mut bs: BitSlicedEnumVec<_> = v.iter().cloned().collect();
+        bs.insert(40, Some((true, false)));
+        v.insert(40, Some((true, false)));
+        assert_eq!(bs.remove(7), v.remove(7));
+        bs.swap(0, 300);
+        v.swap(0, 300);
+        bs.retain(|x| *x != Some((false, false)));
+        v.retain(|x| *x != Some((false, false)));
+        bs.extend_from_slice(&[None, Some((true, true))]);
+        v.extend_from_slice(&[None, Some((true, true))]);
+        assert_eq!(bs.to_vec(), v);
+        bs.resize(500, Some((false, true)));
+        v.resize(500, Some((false, true)));
+        assert_eq!(bs.to_vec(), v);
+        assert_eq!(bs.count(Some((false, true))),
+                   v.iter().filter(|x| **x == Some((false, true))).count());
+        bs.resize(3, None);
+        v.truncate(3);
+        assert_eq!(bs.to_vec(), v);
+    }
+}
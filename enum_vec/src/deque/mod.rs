@@ -0,0 +1,367 @@
+//! A double-ended queue of enums, stored in a ring buffer over an `EnumVec`.
+
+use enum_like::EnumLike;
+use std::fmt;
+use std::iter::FromIterator;
+use vec_u32::EnumVec;
+
+// Capacity of the first allocation of a growable deque
+const MIN_CAPACITY: usize = 16;
+
+/// A double-ended queue of enums, implemented as a ring buffer over an
+/// `EnumVec`, so elements can be added and removed at both ends without
+/// moving the others.
+///
+/// A deque created with `bounded` never grows: when it is full, adding an
+/// element at one end removes the oldest element at the other end.
+///
+/// ```
+/// use enum_vec::EnumDeque;
+///
+/// let mut d = EnumDeque::new();
+/// d.push_back(true);
+/// d.push_front(false);
+/// assert_eq!(d.iter().collect::<Vec<_>>(), vec![false, true]);
+/// assert_eq!(d.pop_front(), Some(false));
+/// assert_eq!(d.pop_back(), Some(true));
+/// assert_eq!(d.pop_back(), None);
+/// ```
+#[derive(Clone)]
+pub struct EnumDeque<T: EnumLike> {
+    // All the slots are elements of buf, so buf.len() is the capacity
+    buf: EnumVec<T>,
+    // Slot of the first element
+    head: usize,
+    len: usize,
+    bounded: bool,
+}
+
+impl<T: EnumLike> EnumDeque<T> {
+    /// Create an empty deque.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Create an empty deque with space for at least `n` elements.
+    pub fn with_capacity(n: usize) -> Self {
+        Self {
+            buf: EnumVec::from_elem(T::from_discr(0), n),
+            head: 0,
+            len: 0,
+            bounded: false,
+        }
+    }
+
+    /// Create an empty deque which holds at most `n` elements. When it is
+    /// full, `push_back` removes the first element and `push_front` removes
+    /// the last element.
+    /// ```
+    /// use enum_vec::EnumDeque;
+    ///
+    /// let mut history = EnumDeque::bounded(3);
+    /// for &x in &[None, Some(true), Some(false), Some(true)] {
+    ///     history.push_back(x);
+    /// }
+    /// assert!(history.is_full());
+    /// assert_eq!(history.iter().collect::<Vec<_>>(), vec![Some(true), Some(false), Some(true)]);
+    /// ```
+    pub fn bounded(n: usize) -> Self {
+        Self {
+            bounded: true,
+            ..Self::with_capacity(n)
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements that fit without allocating. For a
+    /// bounded deque this is the maximum length.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if the length is equal to the capacity.
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    // Slot of element i, which may be one past the end
+    fn slot(&self, i: usize) -> usize {
+        let slot = self.head + i;
+        if slot >= self.capacity() {
+            slot - self.capacity()
+        } else {
+            slot
+        }
+    }
+
+    // Move the elements to a larger buffer, starting at slot 0
+    fn grow(&mut self) {
+        let capacity = ::std::cmp::max(self.capacity() * 2, MIN_CAPACITY);
+        let mut buf = EnumVec::with_capacity(capacity);
+        buf.extend(self.iter());
+        buf.resize(capacity, T::from_discr(0));
+        self.buf = buf;
+        self.head = 0;
+    }
+
+    /// Returns the element at position `i`, counting from the front.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+
+        self.buf.get(self.slot(i))
+    }
+
+    /// Set the element at position `i` to `x`.
+    ///
+    /// # Panics
+    ///
+    /// If `i` is out of bounds.
+    pub fn set(&mut self, i: usize, x: T) {
+        if i >= self.len {
+            panic!("index out of bounds: {} >= {}", i, self.len);
+        }
+        let slot = self.slot(i);
+        self.buf.set(slot, x);
+    }
+
+    /// Append an element at the back. If the deque is bounded and full, the
+    /// first element is removed.
+    pub fn push_back(&mut self, x: T) {
+        if self.is_full() {
+            if !self.bounded {
+                self.grow();
+            } else if self.pop_front().is_none() {
+                // Zero capacity
+                return;
+            }
+        }
+        let slot = self.slot(self.len);
+        self.buf.set(slot, x);
+        self.len += 1;
+    }
+
+    /// Prepend an element at the front. If the deque is bounded and full,
+    /// the last element is removed.
+    pub fn push_front(&mut self, x: T) {
+        if self.is_full() {
+            if !self.bounded {
+                self.grow();
+            } else if self.pop_back().is_none() {
+                return;
+            }
+        }
+        self.head = self.slot(self.capacity() - 1);
+        self.buf.set(self.head, x);
+        self.len += 1;
+    }
+
+    /// Remove the first element and return it.
+    pub fn pop_front(&mut self) -> Option<T> {
+        let x = self.get(0)?;
+        self.head = self.slot(1);
+        self.len -= 1;
+
+        Some(x)
+    }
+
+    /// Remove the last element and return it.
+    pub fn pop_back(&mut self) -> Option<T> {
+        let x = self.get(self.len.checked_sub(1)?)?;
+        self.len -= 1;
+
+        Some(x)
+    }
+
+    /// Remove all the elements, keeping the capacity.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Iterator over the elements, from front to back.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            d: self,
+            front: 0,
+            back: self.len,
+        }
+    }
+
+    /// Copies the elements into an `EnumVec`, from front to back.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<T: EnumLike> Default for EnumDeque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: EnumLike> From<EnumVec<T>> for EnumDeque<T> {
+    fn from(v: EnumVec<T>) -> Self {
+        let len = v.len();
+
+        Self {
+            buf: v,
+            head: 0,
+            len,
+            bounded: false,
+        }
+    }
+}
+
+impl<T: EnumLike> FromIterator<T> for EnumDeque<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<EnumVec<T>>())
+    }
+}
+
+impl<T: EnumLike> Extend<T> for EnumDeque<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.push_back(x);
+        }
+    }
+}
+
+impl<T: EnumLike> PartialEq for EnumDeque<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .iter()
+                .map(T::to_discr)
+                .eq(other.iter().map(T::to_discr))
+    }
+}
+
+impl<T: EnumLike> Eq for EnumDeque<T> {}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for EnumDeque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for &'a EnumDeque<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over EnumDeque
+pub struct Iter<'a, T: 'a + EnumLike> {
+    d: &'a EnumDeque<T>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let x = self.d.get(self.front);
+        self.front += 1;
+
+        x
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+
+        self.d.get(self.back)
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn matches_vec_deque() {
+        let mut d = EnumDeque::new();
+        let mut expected = VecDeque::new();
+        for i in 0..500usize {
+            let x = <Option<(bool, bool)>>::from_discr(i * i % 5);
+            match i % 7 {
+                0 | 3 | 5 => {
+                    d.push_front(x);
+                    expected.push_front(x);
+                }
+                1 | 2 | 6 => {
+                    d.push_back(x);
+                    expected.push_back(x);
+                }
+                _ => {}
+            }
+            if i % 11 == 0 {
+                assert_eq!(d.pop_front(), expected.pop_front());
+            }
+            if i % 13 == 0 {
+                assert_eq!(d.pop_back(), expected.pop_back());
+            }
+        }
+        assert_eq!(
+            d.iter().collect::<Vec<_>>(),
+            expected.iter().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            d.iter().rev().collect::<Vec<_>>(),
+            expected.iter().rev().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(d.get(d.len()), None);
+        d.set(0, None);
+        assert_eq!(d.get(0), Some(None));
+    }
+
+    #[test]
+    fn bounded() {
+        let mut d = EnumDeque::bounded(40);
+        d.extend((0..100).map(|i| i % 3 == 0));
+        assert_eq!(d.capacity(), 40);
+        let expected: Vec<_> = (60..100).map(|i| i % 3 == 0).collect();
+        assert_eq!(d.to_enum_vec().to_vec(), expected);
+
+        d.push_front(true);
+        assert_eq!(
+            (d.len(), d.get(0), d.get(39)),
+            (40, Some(true), Some(expected[38]))
+        );
+
+        let mut empty = EnumDeque::bounded(0);
+        empty.push_back(true);
+        empty.push_front(true);
+        assert!(empty.is_empty());
+    }
+}
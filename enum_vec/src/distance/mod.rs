@@ -0,0 +1,215 @@
+//! Element-wise comparison of two `EnumVec` with the same length.
+//!
+//! The blocks of both vectors are xored, so the elements which are equal
+//! become zero, and then the non-zero elements of each block are counted at
+//! once, as in `EnumVec::count`.
+
+use enum_like::EnumLike;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+fn check_len<T: EnumLike>(a: &EnumVec<T>, b: &EnumVec<T>) {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "length mismatch: {} != {}",
+        a.len(),
+        b.len()
+    );
+}
+
+// Block where the highest bit of each element is set if the elements of a
+// and b at that position are different
+fn mismatch_flags<T: EnumLike>(a: &EnumVec<T>, b: &EnumVec<T>, block: usize) -> u32 {
+    let v = a.masked_block(block) ^ b.masked_block(block);
+
+    packing::nonzero_elements(EnumVec::<T>::BITS_PER_ELEM, v)
+}
+
+fn num_blocks<T: EnumLike>(a: &EnumVec<T>) -> usize {
+    packing::blocks_for_elements(EnumVec::<T>::BITS_PER_ELEM, a.len())
+}
+
+/// Number of positions where the elements of `a` and `b` are different.
+///
+/// # Panics
+///
+/// If the vectors have different lengths.
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::distance::hamming;
+///
+/// let a: EnumVec<bool> = "110010".parse().unwrap();
+/// let b: EnumVec<bool> = "100011".parse().unwrap();
+/// assert_eq!(hamming(&a, &b), 2);
+/// ```
+pub fn hamming<T: EnumLike>(a: &EnumVec<T>, b: &EnumVec<T>) -> usize {
+    check_len(a, b);
+
+    (0..num_blocks(a))
+        .map(|block| mismatch_flags(a, b, block).count_ones() as usize)
+        .sum()
+}
+
+/// Returns the hamming distance between `a` and `b` if it is at most `max`,
+/// or `None` otherwise. The comparison stops as soon as the distance is
+/// greater than `max`.
+///
+/// # Panics
+///
+/// If the vectors have different lengths.
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::distance::hamming_within;
+///
+/// let a: EnumVec<bool> = "110010".parse().unwrap();
+/// let b: EnumVec<bool> = "100011".parse().unwrap();
+/// assert_eq!(hamming_within(&a, &b, 2), Some(2));
+/// assert_eq!(hamming_within(&a, &b, 1), None);
+/// ```
+pub fn hamming_within<T: EnumLike>(
+    a: &EnumVec<T>,
+    b: &EnumVec<T>,
+    max: usize,
+) -> Option<usize> {
+    check_len(a, b);
+
+    let mut distance = 0;
+    for block in 0..num_blocks(a) {
+        distance += mismatch_flags(a, b, block).count_ones() as usize;
+        if distance > max {
+            return None;
+        }
+    }
+
+    Some(distance)
+}
+
+/// Number of positions where the elements of `a` and `b` are equal.
+///
+/// # Panics
+///
+/// If the vectors have different lengths.
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::distance::match_count;
+///
+/// let a: EnumVec<_> = vec![Some(true), None, Some(false)].into();
+/// let b: EnumVec<_> = vec![Some(true), Some(false), Some(false)].into();
+/// assert_eq!(match_count(&a, &b), 2);
+/// ```
+pub fn match_count<T: EnumLike>(a: &EnumVec<T>, b: &EnumVec<T>) -> usize {
+    a.len() - hamming(a, b)
+}
+
+/// Iterator over the positions where the elements of `a` and `b` are
+/// different, in increasing order.
+///
+/// # Panics
+///
+/// If the vectors have different lengths.
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::distance::mismatch_positions;
+///
+/// let a: EnumVec<bool> = "110010".parse().unwrap();
+/// let b: EnumVec<bool> = "100011".parse().unwrap();
+/// let v: Vec<_> = mismatch_positions(&a, &b).collect();
+/// assert_eq!(v, vec![1, 5]);
+/// ```
+pub fn mismatch_positions<'a, T: EnumLike>(
+    a: &'a EnumVec<T>,
+    b: &'a EnumVec<T>,
+) -> MismatchPositions<'a, T> {
+    check_len(a, b);
+
+    MismatchPositions {
+        a,
+        b,
+        block: 0,
+        flags: 0,
+    }
+}
+
+/// Iterator returned by `mismatch_positions`
+pub struct MismatchPositions<'a, T: 'a + EnumLike> {
+    a: &'a EnumVec<T>,
+    b: &'a EnumVec<T>,
+    // The next block to compare
+    block: usize,
+    // The mismatches of block - 1 which were not returned yet
+    flags: u32,
+}
+
+impl<'a, T: EnumLike> Iterator for MismatchPositions<'a, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.flags == 0 {
+            if self.block == num_blocks(self.a) {
+                return None;
+            }
+            self.flags = mismatch_flags(self.a, self.b, self.block);
+            self.block += 1;
+        }
+        let shift = self.flags.trailing_zeros() as usize;
+        // Clear the lowest set bit
+        self.flags &= self.flags - 1;
+
+        Some((self.block - 1) * EnumVec::<T>::ELEMS_PER_BLOCK
+            + shift / EnumVec::<T>::BITS_PER_ELEM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check<T: EnumLike + PartialEq>(va: &[T], vb: &[T]) {
+        let expected: Vec<_> = (0..va.len()).filter(|&i| va[i] != vb[i]).collect();
+        let a = EnumVec::from(va.to_vec());
+        let b = EnumVec::from(vb.to_vec());
+        assert_eq!(hamming(&a, &b), expected.len());
+        assert_eq!(match_count(&a, &b), va.len() - expected.len());
+        assert_eq!(mismatch_positions(&a, &b).collect::<Vec<_>>(), expected);
+        assert_eq!(hamming_within(&a, &b, expected.len()), Some(expected.len()));
+        if !expected.is_empty() {
+            assert_eq!(hamming_within(&a, &b, expected.len() - 1), None);
+        }
+    }
+
+    #[test]
+    fn matches_naive() {
+        for &n in &[0, 1, 10, 11, 100, 333] {
+            let va: Vec<_> = (0..n).map(|i: usize| i * i % 7 < 3).collect();
+            let vb: Vec<_> = (0..n).map(|i: usize| i % 5 < 2).collect();
+            check(&va, &vb);
+            check(&va, &va);
+            let va: Vec<_> = (0..n)
+                .map(|i: usize| <Option<(bool, bool)>>::from_discr(i * i % 5))
+                .collect();
+            let vb: Vec<_> = (0..n)
+                .map(|i: usize| <Option<(bool, bool)>>::from_discr(i % 3))
+                .collect();
+            check(&va, &vb);
+        }
+    }
+
+    #[test]
+    fn ignores_unused_bits() {
+        // pop leaves the old element in the storage
+        let mut a: EnumVec<_> = vec![Some(true); 5].into();
+        let b: EnumVec<_> = vec![Some(true); 5].into();
+        a.push(None);
+        a.pop();
+        assert_eq!(hamming(&a, &b), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn different_lengths() {
+        let a: EnumVec<_> = vec![true; 10].into();
+        let b: EnumVec<_> = vec![true; 11].into();
+        hamming(&a, &b);
+    }
+}
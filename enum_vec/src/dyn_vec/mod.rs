@@ -0,0 +1,411 @@
+use enum_like::EnumLike;
+use std::fmt;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+// Same storage as vec_u32::EnumVec, so the conversion is free
+type StorageBlock = u32;
+const STORAGE_BLOCK_SIZE: usize = 32;
+
+/// A vector of integers in the range `0..num_values`, where `num_values` is
+/// chosen at runtime.
+///
+/// This is the untyped version of `EnumVec`: it uses the same packing, with
+/// the number of bits per element computed from `num_values` when the vector
+/// is created. Useful when the size of the alphabet is only known at runtime,
+/// for example the number of distinct labels in a file.
+///
+/// ```
+/// use enum_vec::DynEnumVec;
+///
+/// let mut v = DynEnumVec::new(5);
+/// assert_eq!(v.bits_per_elem(), 3);
+/// v.push(4);
+/// v.push(0);
+/// v.push(4);
+/// assert_eq!(v.get(1), Some(0));
+/// assert_eq!(v.count(4), 2);
+/// assert_eq!(v.to_vec(), vec![4, 0, 4]);
+/// ```
+#[derive(Clone)]
+pub struct DynEnumVec {
+    // As in EnumVec, the storage may have more blocks than needed, and the
+    // bits after the last element are undefined
+    storage: Vec<StorageBlock>,
+    num_elements: usize,
+    num_values: usize,
+    bits: usize,
+}
+
+impl DynEnumVec {
+    /// Creates an empty vector which can store the values `0..num_values`.
+    ///
+    /// # Panics
+    ///
+    /// If `num_values` is 0, or if it needs more than 32 bits.
+    pub fn new(num_values: usize) -> Self {
+        Self::with_capacity(num_values, 0)
+    }
+
+    /// Creates an empty vector which can store the values `0..num_values`,
+    /// with space for at least `n` elements.
+    ///
+    /// # Panics
+    ///
+    /// If `num_values` is 0, or if it needs more than 32 bits.
+    pub fn with_capacity(num_values: usize, n: usize) -> Self {
+        assert!(num_values != 0, "num_values must be at least 1");
+        // A single value still uses 1 bit, zero sized elements are not
+        // supported
+        let bits = ::std::cmp::max(1, bits_needed_for(num_values - 1));
+        assert!(
+            bits <= STORAGE_BLOCK_SIZE,
+            "num_values too large: {} bits per element",
+            bits
+        );
+        Self {
+            storage: Vec::with_capacity(packing::blocks_for_elements(bits, n)),
+            num_elements: 0,
+            num_values,
+            bits,
+        }
+    }
+
+    /// Creates a vector with `n` copies of `x`.
+    pub fn from_elem(num_values: usize, x: usize, n: usize) -> Self {
+        let mut v = Self::with_capacity(num_values, n);
+        for _ in 0..n {
+            v.push(x);
+        }
+
+        v
+    }
+
+    /// The values of this vector are in the range `0..num_values()`.
+    pub fn num_values(&self) -> usize {
+        self.num_values
+    }
+
+    /// How many bits are used to store each element.
+    pub fn bits_per_elem(&self) -> usize {
+        self.bits
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.num_elements
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    /// Returns the number of elements which can be stored without
+    /// reallocating.
+    pub fn capacity(&self) -> usize {
+        self.storage
+            .capacity()
+            .saturating_mul(packing::elems_per_block(self.bits))
+    }
+
+    /// Returns a reference to the underlying storage, with the same layout
+    /// as `EnumVec::storage`.
+    pub fn storage(&self) -> &[StorageBlock] {
+        &self.storage
+    }
+
+    fn check_value(&self, x: usize) {
+        if x >= self.num_values {
+            panic!("value out of range: {} >= {}", x, self.num_values);
+        }
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<usize> {
+        if i >= self.len() {
+            return None;
+        }
+
+        Some(unsafe { packing::get_unchecked(&self.storage, self.bits, i) })
+    }
+
+    /// Sets the element at position `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()` or `x >= self.num_values()`.
+    pub fn set(&mut self, i: usize, x: usize) {
+        if i >= self.len() {
+            panic!("index out of bounds: {} >= {}", i, self.len());
+        }
+        self.check_value(x);
+
+        unsafe {
+            packing::set_unchecked(&mut self.storage, self.bits, i, x);
+        }
+    }
+
+    /// Appends an element to the end of the vector.
+    ///
+    /// # Panics
+    ///
+    /// If `x >= self.num_values()`.
+    pub fn push(&mut self, x: usize) {
+        self.check_value(x);
+        let i = self.len();
+        if packing::blocks_for_elements(self.bits, i + 1) > self.storage.len() {
+            self.storage.push(0);
+        }
+        self.num_elements += 1;
+
+        unsafe {
+            packing::set_unchecked(&mut self.storage, self.bits, i, x);
+        }
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop(&mut self) -> Option<usize> {
+        let x = self.get(self.len().checked_sub(1)?);
+        self.num_elements -= 1;
+
+        x
+    }
+
+    /// Shortens the vector to `len` elements.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            self.num_elements = len;
+            self.storage
+                .truncate(packing::blocks_for_elements(self.bits, len));
+        }
+    }
+
+    /// Removes all the elements.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Iterator over the elements.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { v: self, idx: 0 }
+    }
+
+    /// Copies `self` into a plain `Vec`.
+    pub fn to_vec(&self) -> Vec<usize> {
+        self.iter().collect()
+    }
+
+    /// Check whether any of the elements is equal to `x`, using the same
+    /// bithacks as `EnumVec::any`.
+    pub fn any(&self, x: usize) -> bool {
+        x < self.num_values && packing::any(&self.storage, self.bits, self.len(), x)
+    }
+
+    /// Check whether all of the elements are equal to `x`, using the same
+    /// bithacks as `EnumVec::all`.
+    pub fn all(&self, x: usize) -> bool {
+        if x >= self.num_values {
+            return self.is_empty();
+        }
+
+        packing::all(&self.storage, self.bits, self.len(), x)
+    }
+
+    /// Count the elements equal to `x`, using the same bithacks as
+    /// `EnumVec::count`.
+    pub fn count(&self, x: usize) -> usize {
+        if x >= self.num_values {
+            return 0;
+        }
+
+        packing::count_between(&self.storage, self.bits, x, 0, self.len())
+    }
+
+    /// Converts into a typed `EnumVec<T>`.
+    ///
+    /// This only works if every value is a valid discriminant of `T`, which
+    /// is true when `num_values` is at most `T::NUM_VARIANTS`. Otherwise
+    /// `self` is returned unchanged. The elements are not copied if both
+    /// use the same number of bits per element, they are repacked if `T`
+    /// reserves a different number of bits.
+    /// ```
+    /// use enum_vec::DynEnumVec;
+    ///
+    /// let mut v = DynEnumVec::new(3);
+    /// v.push(2);
+    /// v.push(1);
+    /// // Option<bool> has 3 variants
+    /// let ev = v.into_enum_vec::<Option<bool>>().unwrap();
+    /// assert_eq!(ev.to_vec(), vec![None, Some(true)]);
+    ///
+    /// let v = DynEnumVec::new(3);
+    /// assert!(v.into_enum_vec::<bool>().is_err());
+    /// ```
+    pub fn into_enum_vec<T: EnumLike>(self) -> Result<EnumVec<T>, Self> {
+        if self.num_values > T::NUM_VARIANTS {
+            return Err(self);
+        }
+
+        if self.bits != EnumVec::<T>::BITS_PER_ELEM {
+            return Ok(self.iter().map(T::from_discr).collect());
+        }
+
+        // Same layout, and all the values are valid discriminants
+        Ok(unsafe { EnumVec::from_storage(self.storage, self.num_elements) })
+    }
+}
+
+fn bits_needed_for(x: usize) -> usize {
+    (0usize.leading_zeros() - x.leading_zeros()) as usize
+}
+
+impl fmt::Debug for DynEnumVec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for DynEnumVec {
+    fn eq(&self, other: &Self) -> bool {
+        self.num_values == other.num_values
+            && self.len() == other.len()
+            && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for DynEnumVec {}
+
+impl Extend<usize> for DynEnumVec {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+impl<T: EnumLike> From<EnumVec<T>> for DynEnumVec {
+    fn from(v: EnumVec<T>) -> Self {
+        let mut dv = Self::new(T::NUM_VARIANTS);
+        // Keep the layout of EnumVec, which may reserve more bits than
+        // needed for NUM_VARIANTS
+        dv.bits = EnumVec::<T>::BITS_PER_ELEM;
+        let blocks = packing::blocks_for_elements(dv.bits, v.len());
+        dv.storage.extend_from_slice(&v.storage()[..blocks]);
+        dv.num_elements = v.len();
+
+        dv
+    }
+}
+
+impl<'a> IntoIterator for &'a DynEnumVec {
+    type Item = usize;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over &DynEnumVec
+pub struct Iter<'a> {
+    v: &'a DynEnumVec,
+    idx: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let x = self.v.get(self.idx)?;
+        self.idx += 1;
+
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len() - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_per_elem() {
+        assert_eq!(DynEnumVec::new(1).bits_per_elem(), 1);
+        assert_eq!(DynEnumVec::new(2).bits_per_elem(), 1);
+        assert_eq!(DynEnumVec::new(3).bits_per_elem(), 2);
+        assert_eq!(DynEnumVec::new(256).bits_per_elem(), 8);
+        assert_eq!(DynEnumVec::new(257).bits_per_elem(), 9);
+    }
+
+    #[test]
+    fn matches_vec() {
+        // 11 bits per element, with 10 wasted bits in each block
+        let n = 2000;
+        let v: Vec<usize> = (0..1000).map(|i| (i * 7919) % n).collect();
+        let mut dv = DynEnumVec::new(n);
+        dv.extend(v.iter().cloned());
+        assert_eq!(dv.to_vec(), v);
+        for x in &[0, 7, 1999, 42] {
+            let count = v.iter().filter(|&y| y == x).count();
+            assert_eq!(dv.count(*x), count);
+            assert_eq!(dv.any(*x), count != 0);
+        }
+        assert!(!dv.all(0));
+        assert_eq!(dv.count(n), 0);
+        dv.truncate(1);
+        assert!(dv.all(0));
+        assert_eq!(dv.pop(), Some(0));
+        assert_eq!(dv.pop(), None);
+    }
+
+    #[test]
+    fn enum_vec_conversions() {
+        let ev: EnumVec<_> = vec![Some(true), None, Some(false)].into();
+        let dv = DynEnumVec::from(ev.clone());
+        assert_eq!(dv.num_values(), 3);
+        assert_eq!(dv.to_vec(), ev.iter().map(|x| x.to_discr()).collect::<Vec<_>>());
+        assert_eq!(dv.clone().into_enum_vec::<Option<bool>>().unwrap(), ev);
+        assert_eq!(dv.clone().into_enum_vec::<[bool; 2]>().unwrap().len(), 3);
+        // Different number of bits, the elements are repacked
+        let wide = dv.clone().into_enum_vec::<[bool; 3]>().unwrap();
+        assert_eq!(wide.iter().map(|x| x.to_discr()).collect::<Vec<_>>(), dv.to_vec());
+        assert!(dv.into_enum_vec::<bool>().is_err());
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    struct WideBool(bool);
+
+    unsafe impl EnumLike for WideBool {
+        const NUM_VARIANTS: usize = 2;
+        const RESERVED_BITS: usize = 4;
+        fn to_discr(self) -> usize {
+            self.0 as usize
+        }
+        fn from_discr(x: usize) -> Self {
+            WideBool(x != 0)
+        }
+    }
+
+    #[test]
+    fn reserved_bits_round_trip() {
+        let v: Vec<_> = [1, 0, 1, 1].iter().map(|&x| WideBool(x != 0)).collect();
+        let ev = EnumVec::from(v.clone());
+        let dv = DynEnumVec::from(ev.clone());
+        assert_eq!(dv.bits_per_elem(), 4);
+        assert_eq!(dv.to_vec(), vec![1, 0, 1, 1]);
+        assert_eq!(dv.clone().into_enum_vec::<WideBool>().unwrap(), ev);
+        // From the minimum width to the reserved width and back
+        let mut narrow = DynEnumVec::new(2);
+        narrow.extend(dv.iter());
+        assert_eq!(narrow.bits_per_elem(), 1);
+        assert_eq!(narrow.into_enum_vec::<WideBool>().unwrap().to_vec(), v);
+        assert_eq!(dv.into_enum_vec::<bool>().unwrap().to_vec(), vec![true, false, true, true]);
+    }
+}
@@ -0,0 +1,391 @@
+use enum_like::EnumLike;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::marker::PhantomData;
+use vec_u32::{BitVec, EnumVec};
+
+// Number of elements between two checkpoints
+const CHECKPOINT_INTERVAL: usize = 64;
+// Longest allowed code, in bits
+const MAX_CODE_LEN: usize = 32;
+
+/// A read-only vector of enum variants compressed with a canonical Huffman
+/// code.
+///
+/// The code is built from the histogram of the elements, so frequent
+/// variants use less bits than `BITS_PER_ELEM`, and variants which do not
+/// appear do not get a code at all. The codes are limited to 32 bits.
+///
+/// The bitstream is stored in a `BitVec`, with a checkpoint every 64
+/// elements: the bit offset of that element, as a `usize`. So `get(i)` has to
+/// decode at most 64 elements, and `iter` decodes each element once.
+///
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::EntropyEnumVec;
+///
+/// let mut ev = EnumVec::from_elem(None, 1000);
+/// ev.set(10, Some(true));
+/// ev.set(20, Some(false));
+/// let ee = EntropyEnumVec::new(&ev);
+/// assert_eq!(ee.get(10), Some(Some(true)));
+/// assert_eq!(ee.get(11), Some(None));
+/// // None is encoded with 1 bit, instead of 2
+/// assert_eq!(ee.code_len(None), Some(1));
+/// assert!(ee.encoded_bits() < 2 * 1000);
+/// assert_eq!(ee.to_enum_vec(), ev);
+/// ```
+#[derive(Clone)]
+pub struct EntropyEnumVec<T: EnumLike> {
+    bits: BitVec,
+    // Bit offset of every CHECKPOINT_INTERVAL-th element
+    checkpoints: Vec<usize>,
+    len: usize,
+    code: Code,
+    phantom: PhantomData<T>,
+}
+
+// A canonical Huffman code
+#[derive(Clone, Debug)]
+struct Code {
+    // Code length of each discriminant, 0 if it has no code
+    lens: Vec<u8>,
+    codes: Vec<u32>,
+    // The discriminants sorted by (code length, discriminant)
+    sorted: Vec<usize>,
+    // For each code length l: the first code of length l, and the index of
+    // its discriminant in sorted
+    first_code: [u64; MAX_CODE_LEN + 1],
+    first_index: [usize; MAX_CODE_LEN + 1],
+    count: [usize; MAX_CODE_LEN + 1],
+}
+
+impl Code {
+    fn new(histogram: &[usize]) -> Self {
+        let lens = code_lengths(histogram);
+        let mut sorted: Vec<usize> =
+            (0..lens.len()).filter(|&x| lens[x] != 0).collect();
+        sorted.sort_by_key(|&x| (lens[x], x));
+
+        let mut codes = vec![0; lens.len()];
+        let mut first_code = [0; MAX_CODE_LEN + 1];
+        let mut first_index = [0; MAX_CODE_LEN + 1];
+        let mut count = [0; MAX_CODE_LEN + 1];
+        let mut next_code: u64 = 0;
+        let mut prev_len = 0;
+        for (k, &x) in sorted.iter().enumerate() {
+            let l = lens[x] as usize;
+            next_code <<= l - prev_len;
+            if l != prev_len {
+                first_code[l] = next_code;
+                first_index[l] = k;
+                prev_len = l;
+            }
+            codes[x] = next_code as u32;
+            count[l] += 1;
+            next_code += 1;
+        }
+
+        Self {
+            lens,
+            codes,
+            sorted,
+            first_code,
+            first_index,
+            count,
+        }
+    }
+
+    fn encode(&self, bits: &mut BitVec, x: usize) {
+        let l = self.lens[x];
+        let c = self.codes[x];
+        for k in (0..l).rev() {
+            bits.push((c >> k) & 1 == 1);
+        }
+    }
+
+    // Decode the element starting at bit pos, returns the discriminant and
+    // the position of the next element
+    fn decode(&self, bits: &BitVec, pos: usize) -> (usize, usize) {
+        let mut c: u64 = 0;
+        let mut pos = pos;
+        for l in 1..=MAX_CODE_LEN {
+            c = (c << 1) | bits.get(pos).unwrap() as u64;
+            pos += 1;
+            let offset = c.wrapping_sub(self.first_code[l]);
+            if self.count[l] != 0 && offset < self.count[l] as u64 {
+                return (self.sorted[self.first_index[l] + offset as usize], pos);
+            }
+        }
+
+        panic!("invalid code");
+    }
+}
+
+// The code length of each symbol, using Huffman's algorithm. If some code
+// is longer than MAX_CODE_LEN, the weights are halved until it fits.
+fn code_lengths(histogram: &[usize]) -> Vec<u8> {
+    let mut weights = histogram.to_vec();
+    loop {
+        let lens = huffman_lengths(&weights);
+        if lens.iter().all(|&l| l as usize <= MAX_CODE_LEN) {
+            return lens;
+        }
+        for w in &mut weights {
+            // Symbols with weight 1 still need a code
+            *w = (*w + 1) / 2;
+        }
+    }
+}
+
+fn huffman_lengths(weights: &[usize]) -> Vec<u8> {
+    let mut lens = vec![0; weights.len()];
+    let leaves: Vec<usize> =
+        (0..weights.len()).filter(|&x| weights[x] != 0).collect();
+    if leaves.len() == 1 {
+        // A code must have at least one bit
+        lens[leaves[0]] = 1;
+        return lens;
+    }
+
+    // Nodes 0..leaves.len() are the leaves, then the internal nodes, the
+    // last one is the root
+    let mut parent = vec![0; 2 * leaves.len()];
+    let mut heap: BinaryHeap<_> = leaves
+        .iter()
+        .enumerate()
+        .map(|(n, &x)| Reverse((weights[x], n)))
+        .collect();
+    let mut next_node = leaves.len();
+    while heap.len() > 1 {
+        let Reverse((wa, a)) = heap.pop().unwrap();
+        let Reverse((wb, b)) = heap.pop().unwrap();
+        parent[a] = next_node;
+        parent[b] = next_node;
+        heap.push(Reverse((wa + wb, next_node)));
+        next_node += 1;
+    }
+
+    // The parent of a node is always created after the node
+    let mut depth = vec![0; next_node];
+    for n in (0..next_node.saturating_sub(1)).rev() {
+        depth[n] = depth[parent[n]] + 1;
+    }
+    for (n, &x) in leaves.iter().enumerate() {
+        // Will be fixed by code_lengths if it does not fit
+        lens[x] = ::std::cmp::min(depth[n], u8::MAX as usize) as u8;
+    }
+
+    lens
+}
+
+impl<T: EnumLike> EntropyEnumVec<T> {
+    /// Compress the elements of `v`, in O(n + NUM_VARIANTS log NUM_VARIANTS)
+    /// time.
+    pub fn new(v: &EnumVec<T>) -> Self {
+        let mut histogram = vec![0; T::NUM_VARIANTS];
+        for x in v.iter() {
+            histogram[x.to_discr()] += 1;
+        }
+        let code = Code::new(&histogram);
+
+        let mut bits = BitVec::new();
+        let mut checkpoints = Vec::with_capacity(v.len() / CHECKPOINT_INTERVAL + 1);
+        for (i, x) in v.iter().enumerate() {
+            if i % CHECKPOINT_INTERVAL == 0 {
+                checkpoints.push(bits.len());
+            }
+            code.encode(&mut bits, x.to_discr());
+        }
+
+        Self {
+            bits,
+            checkpoints,
+            len: v.len(),
+            code,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the length in bits of the code of `x`, or `None` if `x` does
+    /// not appear in the vector.
+    pub fn code_len(&self, x: T) -> Option<usize> {
+        match self.code.lens[x.to_discr()] {
+            0 => None,
+            l => Some(l as usize),
+        }
+    }
+
+    /// Returns the size of the bitstream, in bits. This does not include the
+    /// checkpoints and the code table.
+    pub fn encoded_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+        let mut pos = self.checkpoints[i / CHECKPOINT_INTERVAL];
+        for _ in 0..i % CHECKPOINT_INTERVAL {
+            pos = self.code.decode(&self.bits, pos).1;
+        }
+
+        Some(T::from_discr(self.code.decode(&self.bits, pos).0))
+    }
+
+    /// Iterator over the elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            v: self,
+            idx: 0,
+            pos: 0,
+        }
+    }
+
+    /// Decompress into an `EnumVec`.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        let mut v = EnumVec::with_capacity(self.len);
+        v.extend(self.iter());
+
+        v
+    }
+
+    /// Copies `self` into a plain `Vec`.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for EntropyEnumVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T: EnumLike> From<&'a EnumVec<T>> for EntropyEnumVec<T> {
+    fn from(v: &'a EnumVec<T>) -> Self {
+        Self::new(v)
+    }
+}
+
+impl<T: EnumLike> From<EntropyEnumVec<T>> for EnumVec<T> {
+    fn from(v: EntropyEnumVec<T>) -> Self {
+        v.to_enum_vec()
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for &'a EntropyEnumVec<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over &EntropyEnumVec
+pub struct Iter<'a, T: 'a + EnumLike> {
+    v: &'a EntropyEnumVec<T>,
+    idx: usize,
+    // Bit offset of the next element
+    pos: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.v.len {
+            return None;
+        }
+        let (x, pos) = self.v.code.decode(&self.v.bits, self.pos);
+        self.idx += 1;
+        self.pos = pos;
+
+        Some(T::from_discr(x))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_skewed() {
+        // Geometric distribution: discriminant d appears about n / 2^(d+1)
+        // times
+        let v: Vec<_> = (1..2000usize)
+            .map(|i| <[bool; 4]>::from_discr(i.trailing_zeros() as usize))
+            .collect();
+        let ev = EnumVec::from(v.clone());
+        let ee = EntropyEnumVec::new(&ev);
+        assert_eq!(ee.len(), v.len());
+        for (i, x) in v.iter().enumerate() {
+            assert_eq!(ee.get(i), Some(*x));
+        }
+        assert_eq!(ee.get(v.len()), None);
+        assert_eq!(ee.to_vec(), v);
+        assert_eq!(EnumVec::from(ee.clone()), ev);
+        assert!(ee.encoded_bits() < v.len() * 2);
+        assert_eq!(ee.code_len(<[bool; 4]>::from_discr(15)), None);
+    }
+
+    #[test]
+    fn degenerate_inputs() {
+        let ee = EntropyEnumVec::new(&EnumVec::<bool>::new());
+        assert!(ee.is_empty());
+        assert_eq!(ee.iter().next(), None);
+
+        let ev = EnumVec::from_elem(Some(false), 130);
+        let ee = EntropyEnumVec::new(&ev);
+        assert_eq!(ee.code_len(Some(false)), Some(1));
+        assert_eq!(ee.encoded_bits(), 130);
+        assert_eq!(ee.get(129), Some(Some(false)));
+        assert_eq!(ee.to_enum_vec(), ev);
+    }
+
+    #[test]
+    fn max_code_len() {
+        // Fibonacci weights give the deepest Huffman trees
+        let mut hist = vec![1, 1];
+        while hist.len() < 45 {
+            let n = hist.len();
+            hist.push(hist[n - 1] + hist[n - 2]);
+        }
+        assert!(huffman_lengths(&hist).iter().any(|&l| l as usize > MAX_CODE_LEN));
+        let code = Code::new(&hist);
+        assert!(code.lens.iter().all(|&l| l != 0 && l as usize <= MAX_CODE_LEN));
+
+        let mut bits = BitVec::new();
+        for x in 0..hist.len() {
+            code.encode(&mut bits, x);
+        }
+        let mut pos = 0;
+        for x in 0..hist.len() {
+            let (y, next) = code.decode(&bits, pos);
+            assert_eq!(y, x);
+            pos = next;
+        }
+        assert_eq!(pos, bits.len());
+    }
+}
@@ -0,0 +1,402 @@
+use enum_like::EnumLike;
+use packed::{self, FormatError, Header, BLOCK_BYTES, HEADER_LEN};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+// Number of blocks read or written at once
+const PAGE_BLOCKS: usize = 256;
+// Default number of pages kept in memory
+const DEFAULT_CACHE_PAGES: usize = 16;
+
+/// An `EnumVec` stored in a file using the packed format, which is read and
+/// written in pages of 256 blocks on demand.
+///
+/// The most recently used pages are kept in a small cache, 16 pages (16 KiB)
+/// by default. Modified pages are written back when they are evicted from
+/// the cache, when calling `flush`, and when the `EnumVecFile` is dropped.
+/// As with `BufWriter`, errors while flushing on drop are ignored, so call
+/// `flush` to handle them.
+///
+/// ```
+/// use enum_vec::EnumVecFile;
+///
+/// let path = std::env::temp_dir().join("enum_vec_file_doctest.evec");
+/// let mut f = EnumVecFile::create(&path).unwrap();
+/// for i in 0..1000 {
+///     f.push(i % 3 == 0).unwrap();
+/// }
+/// f.set(1, true).unwrap();
+/// f.flush().unwrap();
+/// drop(f);
+///
+/// let mut f = EnumVecFile::<bool>::open(&path).unwrap();
+/// assert_eq!(f.len(), 1000);
+/// assert_eq!(f.get(1).unwrap(), Some(true));
+/// assert_eq!(f.iter().filter(|x| *x.as_ref().unwrap()).count(), 335);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct EnumVecFile<T: EnumLike> {
+    file: File,
+    len: usize,
+    // Length stored in the header of the file
+    len_on_disk: usize,
+    // Number of blocks written to the file
+    blocks_on_disk: usize,
+    cache: Vec<Page>,
+    cache_pages: usize,
+    // Counter used to find the least recently used page
+    clock: u64,
+    phantom: PhantomData<T>,
+}
+
+struct Page {
+    index: usize,
+    blocks: Vec<u32>,
+    dirty: bool,
+    last_used: u64,
+}
+
+impl<T: EnumLike> EnumVecFile<T> {
+    const BITS: usize = EnumVec::<T>::BITS_PER_ELEM;
+
+    /// Create an empty file, truncating it if it already exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(&Header::new::<T>(0).to_bytes())?;
+
+        Ok(Self::with_file(file, 0, 0))
+    }
+
+    /// Open an existing file for reading and writing.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut header = [0; HEADER_LEN];
+        packed::read_exact_or_truncated(&mut file, &mut header)?;
+        let header = Header::from_bytes(&header)?;
+        if header.is_stream() {
+            return Err(FormatError::UnknownLength.into());
+        }
+        header.check::<T>()?;
+        let expected_len = header
+            .num_blocks()
+            .checked_mul(BLOCK_BYTES)
+            .and_then(|x| x.checked_add(HEADER_LEN))
+            .ok_or(FormatError::BadHeader)? as u64;
+        if file.metadata()?.len() < expected_len {
+            return Err(FormatError::Truncated.into());
+        }
+
+        Ok(Self::with_file(file, header.len as usize, header.num_blocks()))
+    }
+
+    fn with_file(file: File, len: usize, blocks_on_disk: usize) -> Self {
+        Self {
+            file,
+            len,
+            len_on_disk: len,
+            blocks_on_disk,
+            cache: Vec::new(),
+            cache_pages: DEFAULT_CACHE_PAGES,
+            clock: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Set the maximum number of pages kept in memory. At least one page is
+    /// always kept.
+    pub fn set_cache_pages(&mut self, pages: usize) -> io::Result<()> {
+        self.cache_pages = ::std::cmp::max(1, pages);
+        while self.cache.len() > self.cache_pages {
+            self.evict()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn num_blocks(&self) -> usize {
+        packing::blocks_for_elements(Self::BITS, self.len)
+    }
+
+    // Returns the position in self.cache of the page, loading it if needed
+    fn page(&mut self, index: usize) -> io::Result<usize> {
+        self.clock += 1;
+        if let Some(p) = self.cache.iter().position(|p| p.index == index) {
+            self.cache[p].last_used = self.clock;
+            return Ok(p);
+        }
+        if self.cache.len() >= self.cache_pages {
+            self.evict()?;
+        }
+
+        // Every block before num_blocks is either in the cache or in the
+        // file. Blocks which were never written are zero.
+        let first = index * PAGE_BLOCKS;
+        let on_disk = ::std::cmp::min(
+            PAGE_BLOCKS,
+            self.blocks_on_disk.saturating_sub(first),
+        );
+        let mut bytes = vec![0; on_disk * BLOCK_BYTES];
+        self.file.seek(SeekFrom::Start(Self::block_offset(first)))?;
+        self.file.read_exact(&mut bytes)?;
+        let mut blocks = vec![0; PAGE_BLOCKS];
+        for (block, b) in blocks.iter_mut().zip(bytes.chunks(BLOCK_BYTES)) {
+            *block = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        }
+        self.cache.push(Page {
+            index,
+            blocks,
+            dirty: false,
+            last_used: self.clock,
+        });
+
+        Ok(self.cache.len() - 1)
+    }
+
+    fn block_offset(b: usize) -> u64 {
+        (HEADER_LEN + b * BLOCK_BYTES) as u64
+    }
+
+    // Remove the least recently used page from the cache
+    fn evict(&mut self) -> io::Result<()> {
+        let p = (0..self.cache.len())
+            .min_by_key(|&p| self.cache[p].last_used)
+            .unwrap();
+        self.write_page(p)?;
+        self.cache.swap_remove(p);
+
+        Ok(())
+    }
+
+    fn write_page(&mut self, p: usize) -> io::Result<()> {
+        if !self.cache[p].dirty {
+            return Ok(());
+        }
+        let first = self.cache[p].index * PAGE_BLOCKS;
+        let n = ::std::cmp::min(PAGE_BLOCKS, self.num_blocks() - first);
+        let mut bytes = Vec::with_capacity(n * BLOCK_BYTES);
+        for block in &self.cache[p].blocks[..n] {
+            bytes.extend_from_slice(&block.to_le_bytes());
+        }
+        self.file.seek(SeekFrom::Start(Self::block_offset(first)))?;
+        self.file.write_all(&bytes)?;
+        self.cache[p].dirty = false;
+        self.blocks_on_disk = ::std::cmp::max(self.blocks_on_disk, first + n);
+
+        Ok(())
+    }
+
+    /// Returns the element at position `i`, or `None` if it is out of
+    /// bounds. Returns an error if it cannot be read or if it is not a valid
+    /// discriminant.
+    pub fn get(&mut self, i: usize) -> io::Result<Option<T>> {
+        if i >= self.len {
+            return Ok(None);
+        }
+        let (b, _) = packing::block_index(Self::BITS, i);
+        let p = self.page(b / PAGE_BLOCKS)?;
+        let page = &self.cache[p].blocks;
+        let k = i % EnumVec::<T>::ELEMS_PER_BLOCK;
+        let x = unsafe {
+            packing::get_unchecked(&page[b % PAGE_BLOCKS..], Self::BITS, k)
+        };
+        if x >= T::NUM_VARIANTS {
+            return Err(FormatError::InvalidDiscriminant(i).into());
+        }
+
+        Ok(Some(T::from_discr(x)))
+    }
+
+    /// Sets the element at position `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`.
+    pub fn set(&mut self, i: usize, x: T) -> io::Result<()> {
+        if i >= self.len {
+            panic!("index out of bounds: {} >= {}", i, self.len);
+        }
+        let (b, _) = packing::block_index(Self::BITS, i);
+        let p = self.page(b / PAGE_BLOCKS)?;
+        let page = &mut self.cache[p];
+        unsafe {
+            packing::set_unchecked(
+                &mut page.blocks[b % PAGE_BLOCKS..],
+                Self::BITS,
+                i % EnumVec::<T>::ELEMS_PER_BLOCK,
+                x.to_discr(),
+            );
+        }
+        page.dirty = true;
+
+        Ok(())
+    }
+
+    /// Appends an element to the end of the vector.
+    pub fn push(&mut self, x: T) -> io::Result<()> {
+        self.len += 1;
+        let i = self.len - 1;
+        let r = self.set(i, x);
+        if r.is_err() {
+            self.len -= 1;
+        }
+
+        r
+    }
+
+    /// Iterator over the elements. Each element is read as in `get`.
+    pub fn iter(&mut self) -> Iter<'_, T> {
+        Iter { v: self, idx: 0 }
+    }
+
+    /// Read all the elements into an `EnumVec`.
+    pub fn to_enum_vec(&mut self) -> io::Result<EnumVec<T>> {
+        let mut v = EnumVec::with_capacity(self.len);
+        for x in self.iter() {
+            v.push(x?);
+        }
+
+        Ok(v)
+    }
+
+    /// Write all the modified pages and the header to the file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for p in 0..self.cache.len() {
+            self.write_page(p)?;
+        }
+        if self.len != self.len_on_disk {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&Header::new::<T>(self.len).to_bytes())?;
+            self.len_on_disk = self.len;
+        }
+
+        self.file.flush()
+    }
+}
+
+impl<T: EnumLike> Drop for EnumVecFile<T> {
+    fn drop(&mut self) {
+        let _r = self.flush();
+    }
+}
+
+/// Iterator over the elements of an `EnumVecFile`.
+pub struct Iter<'a, T: 'a + EnumLike> {
+    v: &'a mut EnumVecFile<T>,
+    idx: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let x = self.v.get(self.idx).transpose()?;
+        self.idx += 1;
+
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len() - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn write_file<T: EnumLike>(path: &Path, v: &EnumVec<T>) -> io::Result<()> {
+        v.write_packed(File::create(path)?)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        ::std::env::temp_dir().join(format!("enum_vec_{}_{}.evec", name, ::std::process::id()))
+    }
+
+    #[test]
+    fn matches_enum_vec() {
+        let path = temp_path("matches");
+        // Several pages, and a small cache to force evictions
+        let v: Vec<_> = (0..20_000usize)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i * 31 % 5))
+            .collect();
+        let mut ev = EnumVec::from(v);
+        write_file(&path, &ev).unwrap();
+
+        let mut f = EnumVecFile::open(&path).unwrap();
+        f.set_cache_pages(2).unwrap();
+        assert_eq!(f.to_enum_vec().unwrap(), ev);
+        for i in (0..20_000).step_by(7) {
+            f.set(i, None).unwrap();
+            ev.set(i, None);
+        }
+        for _ in 0..3000 {
+            f.push(Some((true, false))).unwrap();
+            ev.push(Some((true, false)));
+        }
+        assert_eq!(f.get(19_999).unwrap(), ev.get(19_999));
+        assert_eq!(f.get(23_000).unwrap(), None);
+        f.flush().unwrap();
+        drop(f);
+
+        // The file is exactly what write_packed would produce
+        let mut expected = vec![];
+        ev.write_packed(&mut expected).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_errors() {
+        let path = temp_path("errors");
+        let ev: EnumVec<_> = vec![Some(true); 100].into();
+        write_file(&path, &ev).unwrap();
+        assert!(EnumVecFile::<bool>::open(&path).is_err());
+        assert!(EnumVecFile::<Option<bool>>::open(&path).is_ok());
+
+        // Truncate the last block
+        let mut bytes = fs::read(&path).unwrap();
+        let n = bytes.len();
+        bytes.truncate(n - 1);
+        fs::write(&path, &bytes).unwrap();
+        let e = EnumVecFile::<Option<bool>>::open(&path).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Set element 99, the first byte of the last block, to the invalid
+        // discriminant 3
+        bytes.push(0);
+        bytes[n - 4] |= 0b11 << 6;
+        fs::write(&path, &bytes).unwrap();
+        let mut f = EnumVecFile::<Option<bool>>::open(&path).unwrap();
+        assert!(f.get(0).is_ok());
+        assert_eq!(f.get(99).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        drop(f);
+
+        // One element per block, the size of the blocks overflows
+        fs::write(&path, Header::new::<[bool; 17]>(usize::MAX / 2).to_bytes()).unwrap();
+        let e = EnumVecFile::<[bool; 17]>::open(&path).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,485 @@
+//! A 2D grid of enums.
+//!
+//! The cells are stored row by row in an `EnumVec`. Each row is padded to a
+//! whole number of storage blocks, so that the operations on one row can
+//! work on its blocks, like `EnumVec::any` and `EnumVec::count` do.
+
+use enum_like::EnumLike;
+use std::fmt;
+use std::ops::Range;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+/// What happens with the neighbours of a cell at the edge of the grid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// The cells outside the grid are skipped
+    Clip,
+    /// The grid wraps around, so the right neighbour of the last cell of a
+    /// row is the first cell of that row
+    Wrap,
+}
+
+// North, east, south and west
+const NEIGHBOURS_4: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+// Clockwise, starting from north
+const NEIGHBOURS_8: [(isize, isize); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// A grid of `width` x `height` enums.
+///
+/// ```
+/// use enum_vec::EnumGrid;
+///
+/// let mut g = EnumGrid::new(4, 3, false);
+/// g.set(1, 2, true);
+/// g.fill_rect(2..4, 0..2, true);
+/// assert_eq!(g.get(1, 2), Some(true));
+/// assert_eq!(g.get(4, 0), None);
+/// assert_eq!(g.row(0).collect::<Vec<_>>(), vec![false, false, true, true]);
+/// assert_eq!(g.row_count(1, true), 2);
+/// assert_eq!(g.transpose().get(2, 1), Some(true));
+/// ```
+#[derive(Clone)]
+pub struct EnumGrid<T: EnumLike> {
+    v: EnumVec<T>,
+    width: usize,
+    height: usize,
+}
+
+impl<T: EnumLike> EnumGrid<T> {
+    const BITS_PER_ELEM: usize = EnumVec::<T>::BITS_PER_ELEM;
+
+    /// Create a grid with all the cells set to `x`.
+    pub fn new(width: usize, height: usize, x: T) -> Self {
+        let stride = Self::stride_for(width);
+        let len = stride.checked_mul(height).expect("capacity overflow");
+
+        Self {
+            v: EnumVec::from_elem(x, len),
+            width,
+            height,
+        }
+    }
+
+    /// Number of columns.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Number of blocks used by each row
+    fn blocks_per_row(width: usize) -> usize {
+        packing::blocks_for_elements(Self::BITS_PER_ELEM, width)
+    }
+
+    // Distance between the first elements of two consecutive rows
+    fn stride_for(width: usize) -> usize {
+        Self::blocks_per_row(width) * EnumVec::<T>::ELEMS_PER_BLOCK
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * Self::stride_for(self.width) + x
+    }
+
+    fn check_bounds(&self, x: usize, y: usize) {
+        if x >= self.width || y >= self.height {
+            panic!(
+                "index out of bounds: ({}, {}) >= ({}, {})",
+                x, y, self.width, self.height
+            );
+        }
+    }
+
+    /// Returns the cell at column `x` and row `y`.
+    pub fn get(&self, x: usize, y: usize) -> Option<T> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.v.get(self.index(x, y))
+    }
+
+    /// Set the cell at column `x` and row `y` to `value`.
+    ///
+    /// # Panics
+    ///
+    /// If the cell is out of bounds.
+    pub fn set(&mut self, x: usize, y: usize, value: T) {
+        self.check_bounds(x, y);
+        let i = self.index(x, y);
+        self.v.set(i, value);
+    }
+
+    /// Iterator over the cells of row `y`, from left to right.
+    ///
+    /// # Panics
+    ///
+    /// If the row is out of bounds.
+    pub fn row(&self, y: usize) -> Line<'_, T> {
+        self.check_bounds(0, y);
+        Line {
+            v: &self.v,
+            next: self.index(0, y),
+            step: 1,
+            remaining: self.width,
+        }
+    }
+
+    /// Iterator over the cells of column `x`, from top to bottom.
+    ///
+    /// # Panics
+    ///
+    /// If the column is out of bounds.
+    pub fn column(&self, x: usize) -> Line<'_, T> {
+        self.check_bounds(x, 0);
+        Line {
+            v: &self.v,
+            next: x,
+            step: Self::stride_for(self.width),
+            remaining: self.height,
+        }
+    }
+
+    /// Iterator over the 4 neighbours of a cell, as `(x, y, value)`: north,
+    /// east, south and west.
+    ///
+    /// # Panics
+    ///
+    /// If the cell is out of bounds.
+    /// ```
+    /// use enum_vec::{Boundary, EnumGrid};
+    ///
+    /// let g = EnumGrid::new(3, 3, false);
+    /// let n: Vec<_> = g.neighbours4(0, 0, Boundary::Clip).map(|(x, y, _)| (x, y)).collect();
+    /// assert_eq!(n, vec![(1, 0), (0, 1)]);
+    /// let n: Vec<_> = g.neighbours4(0, 0, Boundary::Wrap).map(|(x, y, _)| (x, y)).collect();
+    /// assert_eq!(n, vec![(0, 2), (1, 0), (0, 1), (2, 0)]);
+    /// ```
+    pub fn neighbours4(
+        &self,
+        x: usize,
+        y: usize,
+        boundary: Boundary,
+    ) -> Neighbours<'_, T> {
+        self.neighbours(x, y, boundary, &NEIGHBOURS_4)
+    }
+
+    /// Iterator over the 8 neighbours of a cell, as `(x, y, value)`,
+    /// clockwise starting from north.
+    ///
+    /// # Panics
+    ///
+    /// If the cell is out of bounds.
+    pub fn neighbours8(
+        &self,
+        x: usize,
+        y: usize,
+        boundary: Boundary,
+    ) -> Neighbours<'_, T> {
+        self.neighbours(x, y, boundary, &NEIGHBOURS_8)
+    }
+
+    fn neighbours(
+        &self,
+        x: usize,
+        y: usize,
+        boundary: Boundary,
+        offsets: &'static [(isize, isize)],
+    ) -> Neighbours<'_, T> {
+        self.check_bounds(x, y);
+        Neighbours {
+            grid: self,
+            x,
+            y,
+            boundary,
+            offsets: offsets.iter(),
+        }
+    }
+
+    /// Set all the cells in columns `xs` and rows `ys` to `value`. Complete
+    /// blocks are written at once.
+    ///
+    /// # Panics
+    ///
+    /// If the rectangle is out of bounds.
+    pub fn fill_rect(&mut self, xs: Range<usize>, ys: Range<usize>, value: T) {
+        assert!(
+            xs.start <= xs.end
+                && xs.end <= self.width
+                && ys.start <= ys.end
+                && ys.end <= self.height,
+            "rectangle out of bounds: ({:?}, {:?}) with size ({}, {})",
+            xs,
+            ys,
+            self.width,
+            self.height
+        );
+        let epb = EnumVec::<T>::ELEMS_PER_BLOCK;
+        let discr = value.to_discr();
+        let block = discr as u32 * packing::one_mask(Self::BITS_PER_ELEM);
+        for y in ys {
+            let row = self.index(0, y);
+            let mut x = xs.start;
+            while x < xs.end {
+                let i = row + x;
+                // Rows start at a block boundary
+                if x % epb == 0 && x + epb <= xs.end {
+                    unsafe {
+                        self.v.storage_mut()[i / epb] = block;
+                    }
+                    x += epb;
+                } else {
+                    // The index is in bounds and the discriminant is valid
+                    unsafe {
+                        self.v.set_raw_unchecked(i, discr);
+                    }
+                    x += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns a new grid where the rows are the columns of this grid.
+    pub fn transpose(&self) -> Self {
+        let mut t = Self::new(self.height, self.width, T::from_discr(0));
+        for y in 0..self.height {
+            for (x, cell) in self.row(y).enumerate() {
+                t.set(y, x, cell);
+            }
+        }
+
+        t
+    }
+
+    /// Check whether any cell of row `y` is equal to `x`.
+    ///
+    /// # Panics
+    ///
+    /// If the row is out of bounds.
+    pub fn row_any(&self, y: usize, x: T) -> bool {
+        self.check_bounds(0, y);
+        let first_block = y * Self::blocks_per_row(self.width);
+
+        packing::any(
+            &self.v.storage()[first_block..],
+            Self::BITS_PER_ELEM,
+            self.width,
+            x.to_discr(),
+        )
+    }
+
+    /// Count the cells of row `y` which are equal to `x`.
+    ///
+    /// # Panics
+    ///
+    /// If the row is out of bounds.
+    pub fn row_count(&self, y: usize, x: T) -> usize {
+        self.check_bounds(0, y);
+        let first_block = y * Self::blocks_per_row(self.width);
+
+        self.v.count_raw_between(
+            x.to_discr(),
+            first_block,
+            self.index(self.width, y),
+        )
+    }
+}
+
+// The padding at the end of each row is not compared
+impl<T: EnumLike> PartialEq for EnumGrid<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && (0..self.height).all(|y| {
+                self.row(y)
+                    .map(T::to_discr)
+                    .eq(other.row(y).map(T::to_discr))
+            })
+    }
+}
+
+impl<T: EnumLike> Eq for EnumGrid<T> {}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for EnumGrid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries((0..self.height).map(|y| self.row(y).collect::<Vec<_>>()))
+            .finish()
+    }
+}
+
+/// Iterator over a row or a column of an `EnumGrid`
+pub struct Line<'a, T: 'a + EnumLike> {
+    v: &'a EnumVec<T>,
+    next: usize,
+    step: usize,
+    remaining: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Line<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let x = self.v.get(self.next);
+        self.next += self.step;
+        self.remaining -= 1;
+
+        x
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Line<'a, T> {}
+
+/// Iterator over the neighbours of a cell of an `EnumGrid`
+pub struct Neighbours<'a, T: 'a + EnumLike> {
+    grid: &'a EnumGrid<T>,
+    x: usize,
+    y: usize,
+    boundary: Boundary,
+    offsets: ::std::slice::Iter<'static, (isize, isize)>,
+}
+
+impl<'a, T: EnumLike> Iterator for Neighbours<'a, T> {
+    type Item = (usize, usize, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (width, height) =
+            (self.grid.width as isize, self.grid.height as isize);
+        for &(dx, dy) in &mut self.offsets {
+            let (mut x, mut y) = (self.x as isize + dx, self.y as isize + dy);
+            if self.boundary == Boundary::Wrap {
+                x = x.rem_euclid(width);
+                y = y.rem_euclid(height);
+            }
+            // Negative coordinates become too large, and get returns None
+            let (x, y) = (x as usize, y as usize);
+            if let Some(cell) = self.grid.get(x, y) {
+                return Some((x, y, cell));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(x: usize, y: usize) -> Option<(bool, bool)> {
+        <Option<(bool, bool)>>::from_discr((x * 3 + y * y) % 5)
+    }
+
+    fn grid(width: usize, height: usize) -> EnumGrid<Option<(bool, bool)>> {
+        let mut g = EnumGrid::new(width, height, None);
+        for y in 0..height {
+            for x in 0..width {
+                g.set(x, y, cell(x, y));
+            }
+        }
+
+        g
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let g = grid(23, 7);
+        for y in 0..7 {
+            let expected: Vec<_> = (0..23).map(|x| cell(x, y)).collect();
+            assert_eq!(g.row(y).collect::<Vec<_>>(), expected);
+            for d in 0..5 {
+                let x = <Option<(bool, bool)>>::from_discr(d);
+                let n = expected.iter().filter(|&&c| c == x).count();
+                assert_eq!(g.row_count(y, x), n);
+                assert_eq!(g.row_any(y, x), n > 0);
+            }
+        }
+        let t = g.transpose();
+        assert_eq!((t.width(), t.height()), (7, 23));
+        for x in 0..23 {
+            assert_eq!(
+                g.column(x).collect::<Vec<_>>(),
+                t.row(x).collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(t.transpose(), g);
+    }
+
+    #[test]
+    fn fill_rect() {
+        let mut g = grid(35, 4);
+        g.fill_rect(3..32, 1..3, Some((true, false)));
+        for y in 0..4 {
+            for x in 0..35 {
+                let inside = (3..32).contains(&x) && (1..3).contains(&y);
+                let expected = if inside {
+                    Some((true, false))
+                } else {
+                    cell(x, y)
+                };
+                assert_eq!(g.get(x, y), Some(expected));
+            }
+        }
+        assert_eq!(
+            g.row_count(2, Some((true, false))),
+            29 + (0..3)
+                .chain(32..35)
+                .filter(|&x| cell(x, 2) == Some((true, false)))
+                .count()
+        );
+    }
+
+    #[test]
+    fn neighbours() {
+        let g = grid(4, 3);
+        let n: Vec<_> = g.neighbours8(3, 1, Boundary::Clip).collect();
+        assert_eq!(
+            n,
+            vec![
+                (3, 0, cell(3, 0)),
+                (3, 2, cell(3, 2)),
+                (2, 2, cell(2, 2)),
+                (2, 1, cell(2, 1)),
+                (2, 0, cell(2, 0)),
+            ]
+        );
+        let n: Vec<_> = g
+            .neighbours8(3, 2, Boundary::Wrap)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(
+            n,
+            vec![
+                (3, 1),
+                (0, 1),
+                (0, 2),
+                (0, 0),
+                (3, 0),
+                (2, 0),
+                (2, 2),
+                (2, 1)
+            ]
+        );
+        assert_eq!(g.neighbours4(0, 0, Boundary::Clip).count(), 2);
+    }
+}
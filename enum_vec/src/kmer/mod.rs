@@ -0,0 +1,403 @@
+use enum_like::EnumLike;
+use std::fmt;
+use std::hash::Hash;
+use std::mem;
+use std::ops::{BitAnd, BitOr, Shl, Shr};
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+/// An unsigned integer used to store `k` elements of an `EnumVec`, as
+/// returned by `EnumVec::windows_packed`.
+///
+/// The first element is stored in the most significant bits, so comparing
+/// two codes of the same length compares the discriminants in lexicographic
+/// order.
+pub trait PackedCode:
+    Copy
+    + Eq
+    + Ord
+    + Hash
+    + fmt::Debug
+    + Shl<usize, Output = Self>
+    + Shr<usize, Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+{
+    /// Size of the integer in bits
+    const BITS: usize;
+    /// Convert a discriminant into a code
+    fn from_discr(x: usize) -> Self;
+    /// The lowest bits of the code, truncated to `usize`
+    fn low_bits(self) -> usize;
+    /// A code with the lowest `n` bits set to 1
+    fn low_mask(n: usize) -> Self;
+}
+
+macro_rules! packed_code_impls {
+    ($($t:ty)*) => ($(
+        impl PackedCode for $t {
+            const BITS: usize = mem::size_of::<$t>() * 8;
+            fn from_discr(x: usize) -> Self {
+                x as $t
+            }
+            fn low_bits(self) -> usize {
+                self as usize
+            }
+            fn low_mask(n: usize) -> Self {
+                if n == <Self as PackedCode>::BITS {
+                    !0
+                } else {
+                    (1 << n) - 1
+                }
+            }
+        }
+    )*)
+}
+
+packed_code_impls! { u32 u64 u128 }
+
+impl<T: EnumLike> EnumVec<T> {
+    fn check_code_len<C: PackedCode>(k: usize) {
+        assert!(k != 0, "k must be at least 1");
+        // The code is shifted by BITS_PER_ELEM, which must be less than
+        // C::BITS
+        assert!(
+            k.saturating_mul(Self::BITS_PER_ELEM) <= C::BITS
+                && Self::BITS_PER_ELEM < C::BITS,
+            "{} elements of {} bits do not fit in a {} bit code",
+            k,
+            Self::BITS_PER_ELEM,
+            C::BITS
+        );
+    }
+
+    // Discriminant of element i, read directly from the storage
+    fn discr_at(&self, i: usize) -> usize {
+        debug_assert!(i < self.len());
+        unsafe { packing::get_unchecked(self.storage(), Self::BITS_PER_ELEM, i) }
+    }
+
+    /// Iterator over every window of `k` consecutive elements, as a packed
+    /// integer code. Each code is computed from the previous one using a
+    /// shift and a mask.
+    ///
+    /// # Panics
+    ///
+    /// If `k` is 0, or if `k` elements do not fit in `C`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, true, true].into();
+    /// let codes: Vec<u64> = ev.windows_packed(3).collect();
+    /// assert_eq!(codes, vec![0b101, 0b011]);
+    /// ```
+    pub fn windows_packed<C: PackedCode>(&self, k: usize) -> WindowsPacked<'_, T, C> {
+        Self::check_code_len::<C>(k);
+        let bits = Self::BITS_PER_ELEM;
+        // The first k - 1 elements, the next call shifts in one more
+        let n = ::std::cmp::min(k - 1, self.len());
+        let mut code = C::from_discr(0);
+        for i in 0..n {
+            code = (code << bits) | C::from_discr(self.discr_at(i));
+        }
+
+        WindowsPacked {
+            v: self,
+            next: n,
+            code,
+            mask: C::low_mask(k * bits),
+        }
+    }
+
+    /// Iterator over non-overlapping chunks of `k` elements, as a packed
+    /// integer code. When the length is not a multiple of `k`, the last
+    /// chunk is shorter and its code only contains the remaining elements.
+    ///
+    /// # Panics
+    ///
+    /// If `k` is 0, or if `k` elements do not fit in `C`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, true, true, true].into();
+    /// let codes: Vec<u32> = ev.chunks_packed(2).collect();
+    /// assert_eq!(codes, vec![0b10, 0b11, 0b1]);
+    /// ```
+    pub fn chunks_packed<C: PackedCode>(&self, k: usize) -> ChunksPacked<'_, T, C> {
+        Self::check_code_len::<C>(k);
+
+        ChunksPacked {
+            v: self,
+            k,
+            next: 0,
+            phantom: ::std::marker::PhantomData,
+        }
+    }
+
+    /// Iterator over every window of `k` consecutive elements, returning the
+    /// minimum of the code of the window and the code of its reverse
+    /// complement. The reverse complement is the window in reverse order,
+    /// with each element replaced by `complement(element)`.
+    ///
+    /// With DNA, this gives the same code to a k-mer and to the k-mer read
+    /// from the opposite strand.
+    ///
+    /// # Panics
+    ///
+    /// If `k` is 0, or if `k` elements do not fit in `C`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// // Complement: false <-> true
+    /// let ev: EnumVec<_> = vec![true, true, false, false].into();
+    /// let codes: Vec<u64> = ev.canonical_windows_packed(2, |x: bool| !x).collect();
+    /// // 11 and its reverse complement 00, 10 and 10, 00 and 11
+    /// assert_eq!(codes, vec![0b00, 0b10, 0b00]);
+    /// ```
+    pub fn canonical_windows_packed<C, F>(
+        &self,
+        k: usize,
+        mut complement: F,
+    ) -> CanonicalWindowsPacked<'_, T, C, F>
+    where
+        C: PackedCode,
+        F: FnMut(T) -> T,
+    {
+        let forward = self.windows_packed(k);
+        let bits = Self::BITS_PER_ELEM;
+        // Element j of the window is stored at position j, starting one
+        // position higher because the next call shifts it right
+        let mut reverse = C::from_discr(0);
+        for j in 0..forward.next {
+            let x = complement(T::from_discr(self.discr_at(j))).to_discr();
+            reverse = reverse | (C::from_discr(x) << (bits * (j + 1)));
+        }
+
+        CanonicalWindowsPacked {
+            forward,
+            reverse,
+            complement,
+            top_shift: bits * (k - 1),
+        }
+    }
+
+    /// Decodes a code of `k` elements, as returned by `windows_packed`.
+    /// Returns `None` if any of the elements is not a valid discriminant.
+    ///
+    /// # Panics
+    ///
+    /// If `k` is 0, or if `k` elements do not fit in `C`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![Some(true), None, Some(false)].into();
+    /// let code: u64 = ev.windows_packed(3).next().unwrap();
+    /// assert_eq!(EnumVec::from_packed_code(code, 3), Some(ev));
+    /// ```
+    pub fn from_packed_code<C: PackedCode>(code: C, k: usize) -> Option<Self> {
+        Self::check_code_len::<C>(k);
+        let bits = Self::BITS_PER_ELEM;
+        let mask = packing::element_mask(bits) as usize;
+        let mut v = Self::with_capacity(k);
+        for j in (0..k).rev() {
+            let x = (code >> (bits * j)).low_bits() & mask;
+            if x >= T::NUM_VARIANTS {
+                return None;
+            }
+            v.push(T::from_discr(x));
+        }
+
+        Some(v)
+    }
+}
+
+/// Iterator returned by `EnumVec::windows_packed`
+pub struct WindowsPacked<'a, T: 'a + EnumLike, C: PackedCode> {
+    v: &'a EnumVec<T>,
+    // Index of the next element shifted into the code
+    next: usize,
+    code: C,
+    mask: C,
+}
+
+impl<'a, T: EnumLike, C: PackedCode> Iterator for WindowsPacked<'a, T, C> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.v.len() {
+            return None;
+        }
+        let x = C::from_discr(self.v.discr_at(self.next));
+        let bits = EnumVec::<T>::BITS_PER_ELEM;
+        self.code = ((self.code << bits) | x) & self.mask;
+        self.next += 1;
+
+        Some(self.code)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike, C: PackedCode> ExactSizeIterator for WindowsPacked<'a, T, C> {}
+
+/// Iterator returned by `EnumVec::chunks_packed`
+pub struct ChunksPacked<'a, T: 'a + EnumLike, C: PackedCode> {
+    v: &'a EnumVec<T>,
+    k: usize,
+    // Index of the first element of the next chunk
+    next: usize,
+    phantom: ::std::marker::PhantomData<C>,
+}
+
+impl<'a, T: EnumLike, C: PackedCode> Iterator for ChunksPacked<'a, T, C> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.v.len() {
+            return None;
+        }
+        let end = ::std::cmp::min(self.v.len(), self.next + self.k);
+        let mut code = C::from_discr(0);
+        for i in self.next..end {
+            let x = C::from_discr(self.v.discr_at(i));
+            code = (code << EnumVec::<T>::BITS_PER_ELEM) | x;
+        }
+        self.next = end;
+
+        Some(code)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.v.len() - self.next + self.k - 1) / self.k;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike, C: PackedCode> ExactSizeIterator for ChunksPacked<'a, T, C> {}
+
+/// Iterator returned by `EnumVec::canonical_windows_packed`
+pub struct CanonicalWindowsPacked<'a, T: 'a + EnumLike, C: PackedCode, F> {
+    forward: WindowsPacked<'a, T, C>,
+    // Code of the reverse complement, updated in the opposite direction
+    reverse: C,
+    complement: F,
+    // Position of the last element of the window
+    top_shift: usize,
+}
+
+impl<'a, T, C, F> Iterator for CanonicalWindowsPacked<'a, T, C, F>
+where
+    T: EnumLike,
+    C: PackedCode,
+    F: FnMut(T) -> T,
+{
+    type Item = C;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let i = self.forward.next;
+        let forward = self.forward.next()?;
+        let x = T::from_discr(self.forward.v.discr_at(i));
+        let x = C::from_discr((self.complement)(x).to_discr());
+        let bits = EnumVec::<T>::BITS_PER_ELEM;
+        self.reverse = (self.reverse >> bits) | (x << self.top_shift);
+
+        Some(::std::cmp::min(forward, self.reverse))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.forward.size_hint()
+    }
+}
+
+impl<'a, T, C, F> ExactSizeIterator for CanonicalWindowsPacked<'a, T, C, F>
+where
+    T: EnumLike,
+    C: PackedCode,
+    F: FnMut(T) -> T,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2 bits per element, like a nucleotide. The complement is 3 - x.
+    type N = [bool; 2];
+
+    fn complement(x: N) -> N {
+        N::from_discr(3 - x.to_discr())
+    }
+
+    fn sequence(n: usize) -> EnumVec<N> {
+        (0..n).map(|i| N::from_discr(i * i % 7 % 4)).collect()
+    }
+
+    #[test]
+    fn windows_match_decoding() {
+        let ev = sequence(100);
+        for &k in &[1, 2, 5, 16, 32] {
+            let codes: Vec<u64> = ev.windows_packed(k).collect();
+            assert_eq!(codes.len(), 100 - k + 1);
+            for (i, &code) in codes.iter().enumerate() {
+                let w = EnumVec::<N>::from_packed_code(code, k).unwrap();
+                assert_eq!(w.to_vec(), ev.to_vec()[i..i + k].to_vec());
+            }
+        }
+        // 64 elements of 2 bits fit in a u128
+        let codes: Vec<u128> = ev.windows_packed(64).collect();
+        assert_eq!(codes.len(), 37);
+        let w = EnumVec::<N>::from_packed_code(codes[36], 64).unwrap();
+        assert_eq!(w.to_vec(), ev.to_vec()[36..].to_vec());
+        assert_eq!(sequence(10).windows_packed::<u64>(11).count(), 0);
+
+        // 3 is not a valid discriminant of Option<bool>
+        assert_eq!(EnumVec::<Option<bool>>::from_packed_code(0b0011u32, 2), None);
+    }
+
+    #[test]
+    fn chunks() {
+        let ev = sequence(10);
+        let codes: Vec<u32> = ev.chunks_packed(4).collect();
+        assert_eq!(codes.len(), 3);
+        let mut decoded = EnumVec::new();
+        for (i, &code) in codes.iter().enumerate() {
+            let k = ::std::cmp::min(4, 10 - i * 4);
+            decoded.extend(EnumVec::<N>::from_packed_code(code, k).unwrap());
+        }
+        assert_eq!(decoded, ev);
+    }
+
+    #[test]
+    fn canonical() {
+        let ev = sequence(50);
+        let k = 7;
+        let canonical: Vec<u64> = ev.canonical_windows_packed(k, complement).collect();
+        let forward: Vec<u64> = ev.windows_packed(k).collect();
+        assert_eq!(canonical.len(), forward.len());
+        for (i, &code) in canonical.iter().enumerate() {
+            let rc: EnumVec<N> = ev.to_vec()[i..i + k]
+                .iter()
+                .rev()
+                .map(|&x| complement(x))
+                .collect();
+            let rc: u64 = rc.windows_packed(k).next().unwrap();
+            assert_eq!(code, ::std::cmp::min(forward[i], rc));
+        }
+
+        // A window and its reverse complement have the same code
+        let rc: EnumVec<N> = ev.iter().rev().map(complement).collect();
+        let mut a: Vec<u64> = ev.canonical_windows_packed(k, complement).collect();
+        let b: Vec<u64> = rc.canonical_windows_packed(k, complement).collect();
+        a.reverse();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    #[should_panic]
+    fn code_too_small() {
+        sequence(10).windows_packed::<u32>(17);
+    }
+}
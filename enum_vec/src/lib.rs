@@ -50,3 +50,102 @@ pub mod vec_u128;
 #[cfg(feature = "smallvec")]
 /// `SmallEnumVec`
 pub mod smallvec_u32;
+
+/// Rank and select queries over an `EnumVec`.
+pub mod rank_select;
+pub use rank_select::RankSelect;
+
+/// Wavelet matrix built from an `EnumVec`.
+pub mod wavelet_matrix;
+pub use wavelet_matrix::WaveletMatrix;
+
+/// Run-length encoded `EnumVec`.
+pub mod rle;
+pub use rle::RleEnumVec;
+
+/// `EnumVec` for vectors where most elements are equal.
+pub mod sparse;
+pub use sparse::SparseEnumVec;
+
+/// `EnumVec` stored as bit planes.
+pub mod bit_sliced;
+pub use bit_sliced::BitSlicedEnumVec;
+
+/// Huffman-compressed `EnumVec`.
+pub mod entropy;
+pub use entropy::EntropyEnumVec;
+
+/// `EnumVec` with the number of bits per element chosen at runtime.
+pub mod dyn_vec;
+pub use dyn_vec::DynEnumVec;
+
+/// `EnumVec` with atomic operations.
+pub mod atomic;
+pub use atomic::AtomicEnumVec;
+/// Alternative implementation of `AtomicEnumVec` with `AtomicU64` storage,
+/// with the same layout as `vec_u64::EnumVec`.
+pub mod atomic_u64;
+
+/// Binary format of an `EnumVec`.
+pub mod packed;
+pub use packed::FormatError;
+
+/// `EnumVec` stored in a file.
+pub mod file;
+pub use file::EnumVecFile;
+
+/// Zero-copy view of an `EnumVec` in the packed format.
+pub mod vec_ref;
+pub use vec_ref::EnumVecRef;
+
+/// Streaming reader and writer for the packed format.
+pub mod stream;
+pub use stream::{EnumReader, EnumWriter};
+
+/// Conversion between an `EnumVec` and a string of symbols.
+pub mod text;
+pub use text::ParseEnumVecError;
+
+/// URL-safe text encoding of an `EnumVec`.
+pub mod base64url;
+pub use base64url::Base64Error;
+
+/// Windows and chunks of an `EnumVec` packed into integers.
+pub mod kmer;
+pub use kmer::PackedCode;
+
+/// Borrowed view of a range of an `EnumVec`.
+pub mod slice;
+pub use slice::EnumSlice;
+
+/// Search for a sequence of elements.
+pub mod search;
+pub use search::MatchIndices;
+
+/// Hamming distance and other element-wise comparisons.
+pub mod distance;
+pub use distance::MismatchPositions;
+
+/// Differences between two versions of an `EnumVec`.
+pub mod patch;
+pub use patch::{EnumPatch, PatchError};
+
+/// Tracking of the modified blocks of an `EnumVec`.
+pub mod tracked;
+pub use tracked::TrackedEnumVec;
+
+/// Persistent `EnumVec` with structural sharing.
+pub mod persistent;
+pub use persistent::PersistentEnumVec;
+
+/// Transactions which can roll back changes to an `EnumVec`.
+pub mod transaction;
+pub use transaction::{Savepoint, Transaction};
+
+/// 2D grid of enums.
+pub mod grid;
+pub use grid::{Boundary, EnumGrid};
+
+/// Double-ended queue of enums.
+pub mod deque;
+pub use deque::EnumDeque;
@@ -0,0 +1,366 @@
+//! The packed format, used to store an `EnumVec` in files or buffers.
+//!
+//! The format is a 24-byte header followed by the storage blocks, with all
+//! the integers in little-endian:
+//!
+//! | Offset | Size | Field                                               |
+//! |--------|------|-----------------------------------------------------|
+//! | 0      | 4    | Magic bytes, `b"EVEC"`                              |
+//! | 4      | 1    | Format version, currently 1                         |
+//! | 5      | 1    | Bytes per block, 4 for `vec_u32::EnumVec`           |
+//! | 6      | 1    | Bits per element                                    |
+//! | 7      | 1    | Flags, see below                                    |
+//! | 8      | 8    | Number of variants of the element type              |
+//! | 16     | 8    | Number of elements                                  |
+//!
+//! The blocks use the same layout as `EnumVec::storage()`, and the unused
+//! bits of the last block are 0. A file with `n` elements always has
+//! `ceil(n / elements_per_block)` blocks.
+//!
+//! A file can be read as an `EnumVec<T>` if it uses the same number of bits
+//! per element, and its number of variants is lower or equal than
+//! `T::NUM_VARIANTS`. So a type which uses `EnumLike::RESERVED_BITS` can add
+//! new variants and still read the old files.
+//!
+//! The only flag is `FLAG_STREAM`, and the other bits must be 0. When the
+//! number of elements is not known before writing the blocks, as in
+//! `EnumWriter::new`, the header sets `FLAG_STREAM` and stores 0 as the
+//! number of elements, and the real number is stored in 8 more bytes after
+//! the last block. These files can be read using `EnumVec::read_packed` and
+//! `EnumReader`, but not `EnumVecRef` or `EnumVecFile`. Readers which do not
+//! know about the flag reject them because of the non-zero flags byte.
+
+use enum_like::EnumLike;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use stream::EnumReader;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+/// Magic bytes at the start of the header
+pub const MAGIC: [u8; 4] = *b"EVEC";
+/// Current version of the format
+pub const VERSION: u8 = 1;
+/// Length of the header in bytes
+pub const HEADER_LEN: usize = 24;
+/// Bytes per block: the format uses the `vec_u32` layout
+pub const BLOCK_BYTES: usize = 4;
+/// Flag set when the number of elements is stored after the last block
+pub const FLAG_STREAM: u8 = 1;
+
+// Maximum number of blocks allocated before reading them
+const MAX_PREALLOC_BLOCKS: usize = 1 << 16;
+
+/// The header of the packed format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Bits used by each element
+    pub bits_per_elem: u8,
+    /// Number of variants of the element type
+    pub num_variants: u64,
+    /// Number of elements, 0 if `stream` is set
+    pub len: u64,
+    /// The number of elements is stored after the last block
+    pub stream: bool,
+}
+
+impl Header {
+    /// Header for `len` elements of type `T`.
+    pub fn new<T: EnumLike>(len: usize) -> Self {
+        Self {
+            bits_per_elem: EnumVec::<T>::BITS_PER_ELEM as u8,
+            num_variants: T::NUM_VARIANTS as u64,
+            len: len as u64,
+            stream: false,
+        }
+    }
+
+    /// Encode the header.
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut b = [0; HEADER_LEN];
+        b[0..4].copy_from_slice(&MAGIC);
+        b[4] = VERSION;
+        b[5] = BLOCK_BYTES as u8;
+        b[6] = self.bits_per_elem;
+        b[7] = if self.stream { FLAG_STREAM } else { 0 };
+        b[8..16].copy_from_slice(&self.num_variants.to_le_bytes());
+        b[16..24].copy_from_slice(&self.len.to_le_bytes());
+
+        b
+    }
+
+    /// Decode a header from the first `HEADER_LEN` bytes of `b`.
+    pub fn from_bytes(b: &[u8]) -> Result<Self, FormatError> {
+        if b.len() < HEADER_LEN {
+            return Err(FormatError::Truncated);
+        }
+        if b[0..4] != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        if b[4] != VERSION {
+            return Err(FormatError::UnsupportedVersion(b[4]));
+        }
+        let bits_ok = b[6] != 0 && b[6] as usize <= BLOCK_BYTES * 8;
+        if b[5] as usize != BLOCK_BYTES || !bits_ok || b[7] & !FLAG_STREAM != 0 {
+            return Err(FormatError::BadHeader);
+        }
+        let mut num_variants = [0; 8];
+        num_variants.copy_from_slice(&b[8..16]);
+        let mut len = [0; 8];
+        len.copy_from_slice(&b[16..24]);
+        let stream = b[7] & FLAG_STREAM != 0;
+        let len = u64::from_le_bytes(len);
+        if stream && len != 0 {
+            return Err(FormatError::BadHeader);
+        }
+
+        Ok(Self {
+            bits_per_elem: b[6],
+            num_variants: u64::from_le_bytes(num_variants),
+            len,
+            stream,
+        })
+    }
+
+    /// Check that the elements can be read as `T`.
+    pub fn check<T: EnumLike>(&self) -> Result<(), FormatError> {
+        if self.bits_per_elem as usize != EnumVec::<T>::BITS_PER_ELEM
+            || self.num_variants > T::NUM_VARIANTS as u64
+        {
+            return Err(FormatError::WrongType {
+                bits_per_elem: self.bits_per_elem,
+                num_variants: self.num_variants,
+            });
+        }
+        if self.len > usize::MAX as u64 {
+            return Err(FormatError::BadHeader);
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the number of elements is stored after the last
+    /// block.
+    pub fn is_stream(&self) -> bool {
+        self.stream
+    }
+
+    /// Number of blocks after the header. Only valid if the number of
+    /// elements is known, see `is_stream`.
+    pub fn num_blocks(&self) -> usize {
+        packing::blocks_for_elements(self.bits_per_elem as usize, self.len as usize)
+    }
+}
+
+/// Error while decoding the packed format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// The input ends before the header or the last block
+    Truncated,
+    /// The input does not start with `MAGIC`
+    BadMagic,
+    /// The version is not supported by this library
+    UnsupportedVersion(u8),
+    /// Invalid block size, reserved byte, or length
+    BadHeader,
+    /// The number of elements is stored after the last block, which is not
+    /// supported by this reader
+    UnknownLength,
+    /// The elements cannot be read as this type
+    WrongType {
+        /// Bits per element in the header
+        bits_per_elem: u8,
+        /// Number of variants in the header
+        num_variants: u64,
+    },
+    /// The element at this index is not a valid discriminant
+    InvalidDiscriminant(usize),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatError::Truncated => write!(f, "unexpected end of input"),
+            FormatError::BadMagic => write!(f, "not a packed EnumVec"),
+            FormatError::UnsupportedVersion(v) => {
+                write!(f, "unsupported format version {}", v)
+            }
+            FormatError::BadHeader => write!(f, "invalid header"),
+            FormatError::UnknownLength => {
+                write!(f, "the number of elements is not in the header")
+            }
+            FormatError::WrongType {
+                bits_per_elem,
+                num_variants,
+            } => write!(
+                f,
+                "wrong element type: {} variants stored using {} bits",
+                num_variants, bits_per_elem
+            ),
+            FormatError::InvalidDiscriminant(i) => {
+                write!(f, "invalid discriminant at index {}", i)
+            }
+        }
+    }
+}
+
+impl Error for FormatError {}
+
+impl From<FormatError> for io::Error {
+    fn from(e: FormatError) -> Self {
+        let kind = match e {
+            FormatError::Truncated => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+
+        io::Error::new(kind, e)
+    }
+}
+
+/// Returns the index of the first element of `block` which is not a valid
+/// discriminant of `T`. Only the first `n` elements of the block are checked.
+pub(crate) fn first_invalid<T: EnumLike>(block: u32, n: usize) -> Option<usize> {
+    let bits = EnumVec::<T>::BITS_PER_ELEM;
+    if T::NUM_VARIANTS == 1 << bits {
+        // Every value is valid
+        return None;
+    }
+    let mask = packing::element_mask(bits);
+
+    (0..n).find(|k| ((block >> (k * bits)) & mask) as usize >= T::NUM_VARIANTS)
+}
+
+impl<T: EnumLike> EnumVec<T> {
+    /// Write the elements using the packed format.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![Some(true), None].into();
+    /// let mut buf = vec![];
+    /// ev.write_packed(&mut buf).unwrap();
+    /// assert_eq!(buf.len(), 24 + 4);
+    /// let ev2 = EnumVec::<Option<bool>>::read_packed(&buf[..]).unwrap();
+    /// assert_eq!(ev, ev2);
+    /// ```
+    pub fn write_packed<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let header = Header::new::<T>(self.len());
+        w.write_all(&header.to_bytes())?;
+        for b in 0..header.num_blocks() {
+            w.write_all(&self.masked_block(b).to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Read an `EnumVec` written using the packed format, checking that
+    /// every element is a valid discriminant.
+    pub fn read_packed<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut header = [0; HEADER_LEN];
+        read_exact_or_truncated(&mut r, &mut header)?;
+        let header = Header::from_bytes(&header)?;
+        if header.is_stream() {
+            return EnumReader::with_header(r, header)?.collect();
+        }
+        header.check::<T>()?;
+        let len = header.len as usize;
+        let num_blocks = header.num_blocks();
+        let elems_per_block = Self::ELEMS_PER_BLOCK;
+
+        // The length in the header is not trusted until the blocks are read
+        let mut storage = Vec::with_capacity(::std::cmp::min(num_blocks, MAX_PREALLOC_BLOCKS));
+        let mut block = [0; BLOCK_BYTES];
+        for b in 0..num_blocks {
+            read_exact_or_truncated(&mut r, &mut block)?;
+            let block = u32::from_le_bytes(block);
+            let n = ::std::cmp::min(elems_per_block, len - b * elems_per_block);
+            if let Some(k) = first_invalid::<T>(block, n) {
+                return Err(FormatError::InvalidDiscriminant(b * elems_per_block + k).into());
+            }
+            storage.push(block);
+        }
+
+        // All the discriminants were checked
+        Ok(unsafe { Self::from_storage(storage, len) })
+    }
+}
+
+pub(crate) fn read_exact_or_truncated<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<()> {
+    r.read_exact(buf).map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            FormatError::Truncated.into()
+        } else {
+            e
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let v: Vec<_> = (0..1000usize)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i * 7 % 5))
+            .collect();
+        let mut ev = EnumVec::from(v);
+        // Leave garbage after the last element
+        ev.push(None);
+        ev.pop();
+        let mut buf = vec![];
+        ev.write_packed(&mut buf).unwrap();
+        // 3 bits per element, 10 elements per block
+        assert_eq!(buf.len(), HEADER_LEN + 100 * 4);
+        assert_eq!(EnumVec::read_packed(&buf[..]).unwrap(), ev);
+
+        let header = Header::from_bytes(&buf).unwrap();
+        assert_eq!(header, Header::new::<Option<(bool, bool)>>(1000));
+        // A type with more variants but the same bits can read it
+        assert!(header.check::<(bool, Option<bool>)>().is_ok());
+        assert!(header.check::<(bool, bool)>().is_err());
+    }
+
+    #[test]
+    fn invalid_input() {
+        let ev: EnumVec<_> = vec![None, Some(true), Some(false)].into();
+        let mut buf = vec![];
+        ev.write_packed(&mut buf).unwrap();
+
+        let err = |buf: &[u8]| {
+            let e = EnumVec::<Option<bool>>::read_packed(buf).unwrap_err();
+            *e.into_inner().unwrap().downcast::<FormatError>().unwrap()
+        };
+        assert_eq!(err(&buf[..buf.len() - 1]), FormatError::Truncated);
+        let mut bad = buf.clone();
+        bad[0] = b'X';
+        assert_eq!(err(&bad), FormatError::BadMagic);
+        let mut bad = buf.clone();
+        bad[4] = 2;
+        assert_eq!(err(&bad), FormatError::UnsupportedVersion(2));
+        let mut bad = buf.clone();
+        bad[7] = 2;
+        assert_eq!(err(&bad), FormatError::BadHeader);
+        // A streamed file stores 0 as the number of elements
+        let mut bad = buf.clone();
+        bad[7] = FLAG_STREAM;
+        assert_eq!(err(&bad), FormatError::BadHeader);
+        // Set element 1 to the invalid discriminant 3
+        let mut bad = buf.clone();
+        bad[HEADER_LEN] |= 0b11 << 2;
+        assert_eq!(err(&bad), FormatError::InvalidDiscriminant(1));
+        let e = EnumVec::<bool>::read_packed(&buf[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn huge_length() {
+        // Only the header, claiming a huge number of elements
+        let buf = Header::new::<bool>(usize::MAX / 2).to_bytes();
+        let e = EnumVec::<bool>::read_packed(&buf[..]).unwrap_err();
+        assert_eq!(
+            *e.into_inner().unwrap().downcast::<FormatError>().unwrap(),
+            FormatError::Truncated
+        );
+    }
+}
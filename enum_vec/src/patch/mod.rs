@@ -0,0 +1,425 @@
+//! Differences between two versions of an `EnumVec`.
+//!
+//! A patch stores the runs of consecutive elements which changed, together
+//! with the old and new lengths. The changed elements are found by comparing
+//! whole blocks, so unchanged regions are skipped quickly.
+//!
+//! The binary encoding of a patch is:
+//!
+//! * `BITS_PER_ELEM` of the element type, as one byte.
+//! * The old length, the new length and the number of runs, as LEB128
+//!   varints.
+//! * For each run, the number of unchanged elements since the end of the
+//!   previous run and the length of the run, as varints, followed by its
+//!   elements packed into blocks as in the `base64url` module.
+
+use base64url::data_bytes;
+use enum_like::EnumLike;
+use packed::first_invalid;
+use slice::EnumSlice;
+use std::cmp::min;
+use std::error::Error;
+use std::fmt;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+/// Error returned when decoding or applying an `EnumPatch`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// The input ended in the middle of the patch
+    Truncated,
+    /// The patch was encoded for a type with this number of bits per
+    /// element
+    WrongType(u8),
+    /// The runs are out of bounds, or do not include all the new elements
+    BadRun,
+    /// The element at this index of the new vector is not a valid
+    /// discriminant
+    InvalidDiscriminant(usize),
+    /// The patch was created for a vector with a different length
+    LengthMismatch {
+        /// Length of the old vector used to create the patch
+        expected: usize,
+        /// Length of the vector passed to `apply`
+        found: usize,
+    },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::Truncated => write!(f, "truncated patch"),
+            PatchError::WrongType(bits) => {
+                write!(f, "patch for a type with {} bits per element", bits)
+            }
+            PatchError::BadRun => write!(f, "invalid run"),
+            PatchError::InvalidDiscriminant(i) => {
+                write!(f, "invalid discriminant at index {}", i)
+            }
+            PatchError::LengthMismatch { expected, found } => write!(
+                f,
+                "patch for a vector of length {}, found length {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+/// The changes needed to transform an `EnumVec` into another one, as
+/// returned by `EnumVec::diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumPatch<T: EnumLike> {
+    old_len: usize,
+    new_len: usize,
+    // Sorted, non-overlapping and non-adjacent runs of the new elements
+    runs: Vec<(usize, EnumVec<T>)>,
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut x: usize) {
+    loop {
+        let b = (x & 0x7F) as u8;
+        x >>= 7;
+        if x == 0 {
+            bytes.push(b);
+            return;
+        }
+        bytes.push(b | 0x80);
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<usize, PatchError> {
+    let mut x: u64 = 0;
+    for i in 0..10 {
+        let (&b, rest) = bytes.split_first().ok_or(PatchError::Truncated)?;
+        *bytes = rest;
+        // The 10th byte only has the highest bit of the u64
+        if i == 9 && b > 1 {
+            return Err(PatchError::BadRun);
+        }
+        x |= ((b & 0x7F) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return if x > usize::MAX as u64 {
+                Err(PatchError::BadRun)
+            } else {
+                Ok(x as usize)
+            };
+        }
+    }
+
+    Err(PatchError::BadRun)
+}
+
+impl<T: EnumLike> EnumPatch<T> {
+    /// Length of the vector the patch applies to.
+    pub fn old_len(&self) -> usize {
+        self.old_len
+    }
+
+    /// Length of the vector after applying the patch.
+    pub fn new_len(&self) -> usize {
+        self.new_len
+    }
+
+    /// Returns `true` if applying the patch does not change anything.
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty() && self.old_len == self.new_len
+    }
+
+    /// Iterator over the runs of changed elements, as the index of the first
+    /// element of each run and its new values.
+    pub fn changes(&self) -> Changes<'_, T> {
+        Changes {
+            runs: self.runs.iter(),
+        }
+    }
+
+    // Set element i of the new vector, extending the last run if possible
+    fn push_change(&mut self, i: usize, x: T) {
+        if let Some(&mut (start, ref mut run)) = self.runs.last_mut() {
+            if start + run.len() == i {
+                run.push(x);
+                return;
+            }
+        }
+        self.runs.push((i, EnumVec::from_elem(x, 1)));
+    }
+
+    /// Encodes the patch, see the `patch` module.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let bits = EnumVec::<T>::BITS_PER_ELEM;
+        let mut bytes = vec![bits as u8];
+        write_varint(&mut bytes, self.old_len);
+        write_varint(&mut bytes, self.new_len);
+        write_varint(&mut bytes, self.runs.len());
+        let mut end = 0;
+        for &(start, ref run) in &self.runs {
+            write_varint(&mut bytes, start - end);
+            write_varint(&mut bytes, run.len());
+            let blocks = packing::blocks_for_elements(bits, run.len());
+            let n = bytes.len() + data_bytes(bits, run.len()).unwrap();
+            for b in 0..blocks {
+                bytes.extend_from_slice(&run.masked_block(b).to_le_bytes());
+            }
+            bytes.truncate(n);
+            end = start + run.len();
+        }
+
+        bytes
+    }
+
+    /// Decodes a patch created by `to_bytes`, checking that it is valid.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, PatchError> {
+        let bits = EnumVec::<T>::BITS_PER_ELEM;
+        let (&b, rest) = bytes.split_first().ok_or(PatchError::Truncated)?;
+        if b as usize != bits {
+            return Err(PatchError::WrongType(b));
+        }
+        bytes = rest;
+        let old_len = read_varint(&mut bytes)?;
+        let new_len = read_varint(&mut bytes)?;
+        let num_runs = read_varint(&mut bytes)?;
+
+        let mut runs = Vec::with_capacity(min(num_runs, bytes.len() / 2));
+        // The elements after old_len must be included in the runs, so they
+        // must start at old_len and cover all the new elements
+        let mut tail = old_len;
+        let mut end: usize = 0;
+        for _ in 0..num_runs {
+            let gap = read_varint(&mut bytes)?;
+            let len = read_varint(&mut bytes)?;
+            let start = end.checked_add(gap).ok_or(PatchError::BadRun)?;
+            // Runs must not be adjacent nor empty
+            if (gap == 0 && end != 0)
+                || len == 0
+                || new_len - min(start, new_len) < len
+            {
+                return Err(PatchError::BadRun);
+            }
+            let n = data_bytes(bits, len).ok_or(PatchError::BadRun)?;
+            if bytes.len() < n {
+                return Err(PatchError::Truncated);
+            }
+            let (data, rest) = bytes.split_at(n);
+            bytes = rest;
+
+            let epb = EnumVec::<T>::ELEMS_PER_BLOCK;
+            let mut storage = Vec::with_capacity((n + 3) / 4);
+            for (b, c) in data.chunks(4).enumerate() {
+                let mut block = [0; 4];
+                block[..c.len()].copy_from_slice(c);
+                let block = u32::from_le_bytes(block);
+                if let Some(k) =
+                    first_invalid::<T>(block, min(epb, len - b * epb))
+                {
+                    return Err(PatchError::InvalidDiscriminant(
+                        start + b * epb + k,
+                    ));
+                }
+                storage.push(block);
+            }
+            end = start + len;
+            if start <= tail && tail < end {
+                tail = end;
+            }
+            // All the discriminants were checked
+            runs.push((start, unsafe { EnumVec::from_storage(storage, len) }));
+        }
+        if tail < new_len || !bytes.is_empty() {
+            return Err(PatchError::BadRun);
+        }
+
+        Ok(EnumPatch {
+            old_len,
+            new_len,
+            runs,
+        })
+    }
+}
+
+impl<T: EnumLike> EnumVec<T> {
+    /// Returns the changes needed to transform `old` into `new`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let old: EnumVec<bool> = "0000000000".parse().unwrap();
+    /// let new: EnumVec<bool> = "0110000111111".parse().unwrap();
+    /// let patch = EnumVec::diff(&old, &new);
+    /// assert_eq!(patch.changes().count(), 2);
+    ///
+    /// let mut v = old.clone();
+    /// v.apply(&patch).unwrap();
+    /// assert_eq!(v, new);
+    /// ```
+    pub fn diff(old: &Self, new: &Self) -> EnumPatch<T> {
+        let mut patch = EnumPatch {
+            old_len: old.len(),
+            new_len: new.len(),
+            runs: vec![],
+        };
+        let bits = Self::BITS_PER_ELEM;
+        let epb = Self::ELEMS_PER_BLOCK;
+        let common = min(old.len(), new.len());
+        for b in 0..packing::blocks_for_elements(bits, common) {
+            let n = min(epb, common - b * epb);
+            // Only the first n elements are compared
+            let mask =
+                packing::valid_mask(bits) & !(!0u64 << (n * bits)) as u32;
+            let v = (old.storage()[b] ^ new.storage()[b]) & mask;
+            let mut flags = packing::nonzero_elements(bits, v);
+            while flags != 0 {
+                let i = b * epb + flags.trailing_zeros() as usize / bits;
+                patch.push_change(i, new.get(i).unwrap());
+                flags &= flags - 1;
+            }
+        }
+        for i in common..new.len() {
+            patch.push_change(i, new.get(i).unwrap());
+        }
+
+        patch
+    }
+
+    /// Applies a patch created by `diff`, so that the vector becomes equal
+    /// to the new vector used to create the patch.
+    ///
+    /// Returns an error and leaves the vector unchanged if its length is not
+    /// the length of the old vector.
+    pub fn apply(&mut self, patch: &EnumPatch<T>) -> Result<(), PatchError> {
+        if self.len() != patch.old_len {
+            return Err(PatchError::LengthMismatch {
+                expected: patch.old_len,
+                found: self.len(),
+            });
+        }
+
+        self.truncate(patch.new_len);
+        for &(start, ref run) in &patch.runs {
+            for (i, x) in (start..).zip(run) {
+                if i < self.len() {
+                    self.set(i, x);
+                } else {
+                    // The runs include every element after old_len
+                    self.push(x);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator returned by `EnumPatch::changes`
+pub struct Changes<'a, T: 'a + EnumLike> {
+    runs: ::std::slice::Iter<'a, (usize, EnumVec<T>)>,
+}
+
+impl<'a, T: EnumLike> Iterator for Changes<'a, T> {
+    type Item = (usize, EnumSlice<'a, T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runs
+            .next()
+            .map(|&(start, ref run)| (start, run.as_slice()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.runs.size_hint()
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Changes<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(n: usize, seed: usize) -> EnumVec<Option<(bool, bool)>> {
+        (0..n)
+            .map(|i| {
+                let x = if i % 37 < 3 { i * seed } else { i };
+                <Option<(bool, bool)>>::from_discr(x % 5)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_apply() {
+        for &(n_old, n_new) in
+            &[(0, 0), (0, 15), (200, 200), (200, 123), (123, 200)]
+        {
+            let old = world(n_old, 1);
+            let new = world(n_new, 3);
+            let patch = EnumVec::diff(&old, &new);
+            let bytes = patch.to_bytes();
+            let decoded = EnumPatch::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded, patch);
+            let mut v = old.clone();
+            v.apply(&decoded).unwrap();
+            assert_eq!(v, new);
+        }
+
+        let old = world(1000, 1);
+        let patch = EnumVec::diff(&old, &old);
+        assert!(patch.is_empty());
+        assert_eq!(patch.to_bytes().len(), 6);
+        let mut new = old.clone();
+        new.set(500, None);
+        new.set(501, None);
+        let patch = EnumVec::diff(&old, &new);
+        assert_eq!(
+            patch
+                .changes()
+                .map(|(i, run)| (i, run.len()))
+                .collect::<Vec<_>>(),
+            vec![(500, 2)]
+        );
+    }
+
+    #[test]
+    fn invalid_patches() {
+        let old = world(100, 1);
+        let new = world(120, 3);
+        let patch = EnumVec::diff(&old, &new);
+        let mut v = world(99, 1);
+        assert_eq!(
+            v.apply(&patch),
+            Err(PatchError::LengthMismatch {
+                expected: 100,
+                found: 99
+            })
+        );
+        assert_eq!(v, world(99, 1));
+
+        let bytes = patch.to_bytes();
+        assert_eq!(
+            EnumPatch::<Option<(bool, bool)>>::from_bytes(
+                &bytes[..bytes.len() - 1]
+            ),
+            Err(PatchError::Truncated)
+        );
+        assert_eq!(
+            EnumPatch::<bool>::from_bytes(&bytes),
+            Err(PatchError::WrongType(3))
+        );
+        // New length 121 without a run for the last element
+        let mut bad = bytes.clone();
+        bad[2] += 1;
+        assert_eq!(
+            EnumPatch::<Option<(bool, bool)>>::from_bytes(&bad),
+            Err(PatchError::BadRun)
+        );
+        // A run with the single element 7
+        let bad = [3, 1, 1, 1, 0, 1, 7];
+        assert_eq!(
+            EnumPatch::<Option<(bool, bool)>>::from_bytes(&bad),
+            Err(PatchError::InvalidDiscriminant(0))
+        );
+
+        // A varint longer than 64 bits
+        let mut bad = [0xFF; 10];
+        bad[9] = 2;
+        assert_eq!(read_varint(&mut &bad[..]), Err(PatchError::BadRun));
+    }
+}
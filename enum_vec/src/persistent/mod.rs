@@ -0,0 +1,423 @@
+//! A persistent `EnumVec`, where clones share their storage.
+//!
+//! The storage blocks are split into leaves of `LEAF_BLOCKS` blocks, which
+//! are the leaves of a tree where each node has up to `BRANCH` children. All
+//! the nodes are reference counted, so cloning the vector only clones the
+//! root. Modifying an element copies the nodes in the path from the root to
+//! its leaf, if they are shared with another vector, and keeps sharing the
+//! rest of the tree.
+
+use enum_like::EnumLike;
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+const BRANCH_BITS: usize = 5;
+// Number of children of each node
+const BRANCH: usize = 1 << BRANCH_BITS;
+// Number of storage blocks in each leaf
+const LEAF_BLOCKS: usize = 32;
+
+#[derive(Clone)]
+enum Node {
+    // May be shorter than LEAF_BLOCKS
+    Leaf(Arc<Vec<u32>>),
+    Branch(Arc<Vec<Node>>),
+}
+
+impl Node {
+    fn ptr_eq(&self, other: &Node) -> bool {
+        match (self, other) {
+            (Node::Leaf(a), Node::Leaf(b)) => Arc::ptr_eq(a, b),
+            (Node::Branch(a), Node::Branch(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // Append the blocks of all the leaves, in order
+    fn collect_blocks(&self, out: &mut Vec<u32>) {
+        match *self {
+            Node::Leaf(ref blocks) => out.extend_from_slice(blocks),
+            Node::Branch(ref children) => {
+                for child in children.iter() {
+                    child.collect_blocks(out);
+                }
+            }
+        }
+    }
+}
+
+/// A vector of enums with O(1) `clone`, which shares the unmodified parts of
+/// the storage with its clones.
+///
+/// ```
+/// use enum_vec::PersistentEnumVec;
+///
+/// let mut v: PersistentEnumVec<_> = vec![false; 10000].into_iter().collect();
+/// let snapshot = v.clone();
+/// assert!(v.ptr_eq(&snapshot));
+///
+/// v.set(5000, true);
+/// assert!(!v.ptr_eq(&snapshot));
+/// assert_eq!(v.get(5000), Some(true));
+/// assert_eq!(snapshot.get(5000), Some(false));
+/// ```
+pub struct PersistentEnumVec<T: EnumLike> {
+    root: Node,
+    // Height of the tree, 0 when the root is a leaf
+    depth: usize,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+// Derive would require T: Clone
+impl<T: EnumLike> Clone for PersistentEnumVec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            depth: self.depth,
+            len: self.len,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: EnumLike> PersistentEnumVec<T> {
+    const BITS_PER_ELEM: usize = EnumVec::<T>::BITS_PER_ELEM;
+    const LEAF_ELEMS: usize = EnumVec::<T>::ELEMS_PER_BLOCK * LEAF_BLOCKS;
+
+    /// Create an empty vector.
+    pub fn new() -> Self {
+        Self {
+            root: Node::Leaf(Arc::new(vec![])),
+            depth: 0,
+            len: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if both vectors are the same version: one is a clone
+    /// of the other, and neither was modified since then.
+    ///
+    /// Equal vectors created independently are not `ptr_eq`.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.root.ptr_eq(&other.root)
+    }
+
+    // Number of elements that fit in the tree without adding a level
+    fn capacity(&self) -> usize {
+        (0..self.depth).fold(Self::LEAF_ELEMS, |c, _| c.saturating_mul(BRANCH))
+    }
+
+    // The child at each level of the path to leaf l
+    fn child_index(l: usize, level: usize) -> usize {
+        (l >> (BRANCH_BITS * level)) & (BRANCH - 1)
+    }
+
+    fn leaf(&self, l: usize) -> &[u32] {
+        let mut node = &self.root;
+        for level in (0..self.depth).rev() {
+            match *node {
+                Node::Branch(ref children) => {
+                    node = &children[Self::child_index(l, level)];
+                }
+                Node::Leaf(_) => unreachable!(),
+            }
+        }
+        match *node {
+            Node::Leaf(ref blocks) => blocks,
+            Node::Branch(_) => unreachable!(),
+        }
+    }
+
+    // Returns leaf l, copying the nodes in its path which are shared and
+    // creating the missing ones
+    fn leaf_mut(&mut self, l: usize) -> &mut Vec<u32> {
+        let mut node = &mut self.root;
+        for level in (0..self.depth).rev() {
+            let children = match *node {
+                Node::Branch(ref mut children) => Arc::make_mut(children),
+                Node::Leaf(_) => unreachable!(),
+            };
+            let k = Self::child_index(l, level);
+            if k == children.len() {
+                children.push(if level == 0 {
+                    Node::Leaf(Arc::new(vec![]))
+                } else {
+                    Node::Branch(Arc::new(vec![]))
+                });
+            }
+            node = &mut children[k];
+        }
+        match *node {
+            Node::Leaf(ref mut blocks) => Arc::make_mut(blocks),
+            Node::Branch(_) => unreachable!(),
+        }
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+        let leaf = self.leaf(i / Self::LEAF_ELEMS);
+        let discr = unsafe {
+            packing::get_unchecked(
+                leaf,
+                Self::BITS_PER_ELEM,
+                i % Self::LEAF_ELEMS,
+            )
+        };
+
+        Some(T::from_discr(discr))
+    }
+
+    // Set element i, which may be equal to len, without changing the length
+    fn set_raw(&mut self, i: usize, discr: usize) {
+        let leaf = self.leaf_mut(i / Self::LEAF_ELEMS);
+        let i = i % Self::LEAF_ELEMS;
+        let (b, _) = packing::block_index(Self::BITS_PER_ELEM, i);
+        if leaf.len() <= b {
+            leaf.resize(b + 1, 0);
+        }
+        unsafe {
+            packing::set_unchecked(leaf, Self::BITS_PER_ELEM, i, discr);
+        }
+    }
+
+    /// Set the element at position `i` to `x`. If the leaf with that element
+    /// is shared with a clone, it is copied first.
+    ///
+    /// # Panics
+    ///
+    /// If `i` is out of bounds.
+    pub fn set(&mut self, i: usize, x: T) {
+        if i >= self.len {
+            panic!("index out of bounds: {} >= {}", i, self.len);
+        }
+
+        self.set_raw(i, x.to_discr());
+    }
+
+    /// Append an element at the end.
+    pub fn push(&mut self, x: T) {
+        if self.len == self.capacity() {
+            let root = self.root.clone();
+            self.root = Node::Branch(Arc::new(vec![root]));
+            self.depth += 1;
+        }
+        let len = self.len;
+        self.set_raw(len, x.to_discr());
+        self.len += 1;
+    }
+
+    /// Remove the last element and return it.
+    pub fn pop(&mut self) -> Option<T> {
+        let x = self.get(self.len.checked_sub(1)?)?;
+        // The storage is not modified, so it stays shared
+        self.len -= 1;
+
+        Some(x)
+    }
+
+    /// Iterator over the elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            v: self,
+            leaf: &[],
+            idx: 0,
+        }
+    }
+
+    /// Copies the elements into a new `EnumVec`.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        let mut storage = vec![];
+        self.root.collect_blocks(&mut storage);
+        storage.truncate(packing::blocks_for_elements(
+            Self::BITS_PER_ELEM,
+            self.len,
+        ));
+
+        // Each leaf has the valid blocks of its elements
+        unsafe { EnumVec::from_storage(storage, self.len) }
+    }
+}
+
+impl<T: EnumLike> Default for PersistentEnumVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: EnumLike> From<EnumVec<T>> for PersistentEnumVec<T> {
+    fn from(v: EnumVec<T>) -> Self {
+        let bits = EnumVec::<T>::BITS_PER_ELEM;
+        let blocks: Vec<u32> = (0..packing::blocks_for_elements(bits, v.len()))
+            .map(|b| v.masked_block(b))
+            .collect();
+        let mut nodes: Vec<Node> = blocks
+            .chunks(LEAF_BLOCKS)
+            .map(|c| Node::Leaf(Arc::new(c.to_vec())))
+            .collect();
+        let mut depth = 0;
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(BRANCH)
+                .map(|c| Node::Branch(Arc::new(c.to_vec())))
+                .collect();
+            depth += 1;
+        }
+
+        Self {
+            root: nodes.pop().unwrap_or_else(|| Node::Leaf(Arc::new(vec![]))),
+            depth,
+            len: v.len(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: EnumLike> FromIterator<T> for PersistentEnumVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<EnumVec<T>>())
+    }
+}
+
+impl<T: EnumLike> PartialEq for PersistentEnumVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+            || (self.len == other.len
+                && self
+                    .iter()
+                    .map(T::to_discr)
+                    .eq(other.iter().map(T::to_discr)))
+    }
+}
+
+impl<T: EnumLike> Eq for PersistentEnumVec<T> {}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for PersistentEnumVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for &'a PersistentEnumVec<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over PersistentEnumVec
+pub struct Iter<'a, T: 'a + EnumLike> {
+    v: &'a PersistentEnumVec<T>,
+    // The leaf with element idx, when idx is not the first element of a leaf
+    leaf: &'a [u32],
+    idx: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.v.len {
+            return None;
+        }
+        let leaf_elems = PersistentEnumVec::<T>::LEAF_ELEMS;
+        let i = self.idx % leaf_elems;
+        if i == 0 {
+            self.leaf = self.v.leaf(self.idx / leaf_elems);
+        }
+        let bits = PersistentEnumVec::<T>::BITS_PER_ELEM;
+        let discr = unsafe { packing::get_unchecked(self.leaf, bits, i) };
+        self.idx += 1;
+
+        Some(T::from_discr(discr))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elems(n: usize) -> Vec<Option<(bool, bool)>> {
+        (0..n)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i * i % 5))
+            .collect()
+    }
+
+    #[test]
+    fn push_and_convert() {
+        // 320 elements per leaf, so this needs 3 levels
+        let v = elems(320 * 33 * 2);
+        let mut pv = PersistentEnumVec::new();
+        for &x in &v {
+            pv.push(x);
+        }
+        assert_eq!(pv.depth, 2);
+        assert_eq!(pv.iter().collect::<Vec<_>>(), v);
+        assert_eq!(pv.to_enum_vec(), EnumVec::from(v.clone()));
+        let from_vec = PersistentEnumVec::from(EnumVec::from(v.clone()));
+        assert_eq!(from_vec.depth, 2);
+        assert_eq!(from_vec, pv);
+        assert!(!from_vec.ptr_eq(&pv));
+
+        for _ in 0..1000 {
+            pv.pop();
+        }
+        assert_eq!(pv.to_enum_vec().to_vec(), v[..v.len() - 1000].to_vec());
+        assert_eq!(PersistentEnumVec::<bool>::new().to_enum_vec().len(), 0);
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let v = elems(10000);
+        let mut a: PersistentEnumVec<_> = v.iter().cloned().collect();
+        let b = a.clone();
+        a.set(1234, None);
+        a.push(Some((true, true)));
+        let c = a.clone();
+        a.set(0, Some((true, false)));
+
+        assert_eq!(b.iter().collect::<Vec<_>>(), v);
+        assert_eq!(
+            (c.get(1234), c.get(0), c.len()),
+            (Some(None), Some(v[0]), 10001)
+        );
+        assert_eq!(a.get(0), Some(Some((true, false))));
+        assert!(!a.ptr_eq(&c) && !b.ptr_eq(&c));
+
+        // Only the path to the modified leaf was copied
+        match (&a.root, &c.root) {
+            (Node::Branch(x), Node::Branch(y)) => {
+                assert!(!Arc::ptr_eq(x, y));
+                assert!(x[1].ptr_eq(&y[1]));
+                assert!(!x[0].ptr_eq(&y[0]));
+            }
+            _ => panic!("expected a branch"),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use enum_like::EnumLike;
+use vec_u32::EnumVec;
+
+// Number of storage blocks covered by each superblock.
+const SUPERBLOCK_SIZE: usize = 32;
+
+/// An `EnumVec` with an index which allows to answer rank and select queries
+/// quickly.
+///
+/// * `rank(x, i)`: number of elements equal to `x` before position `i`, in
+///   O(1) time.
+/// * `select(x, k)`: position of the `k`-th element equal to `x`, in
+///   O(log n) time.
+///
+/// # Memory overhead
+///
+/// The vector is split into superblocks of 32 storage blocks (1024 bits),
+/// and for each superblock we store the number of times each variant
+/// appears before it, as a `usize`. So the index uses
+/// `T::NUM_VARIANTS * size_of::<usize>()` bytes every 1024 bits of data.
+/// On a 64-bit machine, that is a 12.5% overhead for a `BitVec`, and 25% for
+/// a type with 4 variants. The overhead grows with the number of variants, so
+/// this is only a good idea for small alphabets.
+///
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::RankSelect;
+///
+/// let ev: EnumVec<_> = vec![true, false, true, true, false].into();
+/// let rs = RankSelect::new(ev);
+/// assert_eq!(rs.rank(true, 0), 0);
+/// assert_eq!(rs.rank(true, 3), 2);
+/// assert_eq!(rs.rank(false, 5), 2);
+/// assert_eq!(rs.select(true, 2), Some(3));
+/// assert_eq!(rs.select(false, 2), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RankSelect<T: EnumLike> {
+    v: EnumVec<T>,
+    // counts[s * T::NUM_VARIANTS + x] is the number of elements equal to x
+    // before superblock s. There is one more superblock at the end, with the
+    // total counts.
+    counts: Vec<usize>,
+}
+
+impl<T: EnumLike> RankSelect<T> {
+    /// Build the index over `v`, in O(n) time.
+    pub fn new(v: EnumVec<T>) -> Self {
+        let elems_per_superblock = Self::elems_per_superblock();
+        let num_superblocks = (v.len() + elems_per_superblock - 1) / elems_per_superblock;
+        let mut counts = vec![0; (num_superblocks + 1) * T::NUM_VARIANTS];
+        let mut acc = vec![0; T::NUM_VARIANTS];
+
+        for s in 0..num_superblocks {
+            let start = s * elems_per_superblock;
+            let end = ::std::cmp::min(start + elems_per_superblock, v.len());
+            if T::NUM_VARIANTS <= EnumVec::<T>::ELEMS_PER_BLOCK {
+                // Count each variant using the block bithacks
+                let first_block = s * SUPERBLOCK_SIZE;
+                for (x, count) in acc.iter_mut().enumerate() {
+                    *count += v.count_raw_between(x, first_block, end);
+                }
+            } else {
+                // With many variants it is faster to look at each element
+                for i in start..end {
+                    acc[unsafe { v.get_raw_unchecked(i) }] += 1;
+                }
+            }
+            let next = (s + 1) * T::NUM_VARIANTS;
+            counts[next..next + T::NUM_VARIANTS].copy_from_slice(&acc);
+        }
+
+        Self { v, counts }
+    }
+
+    fn elems_per_superblock() -> usize {
+        SUPERBLOCK_SIZE * EnumVec::<T>::ELEMS_PER_BLOCK
+    }
+
+    fn superblock_count(&self, s: usize, x: usize) -> usize {
+        self.counts[s * T::NUM_VARIANTS + x]
+    }
+
+    /// Returns the number of elements equal to `x` in the range `0..i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i > self.len()`.
+    pub fn rank(&self, x: T, i: usize) -> usize {
+        assert!(i <= self.len(), "index out of bounds: {} > {}", i, self.len());
+        let x = x.to_discr();
+        let s = i / Self::elems_per_superblock();
+        self.superblock_count(s, x)
+            + self.v.count_raw_between(x, s * SUPERBLOCK_SIZE, i)
+    }
+
+    /// Returns the index of the `k`-th element equal to `x`, starting from
+    /// `k = 0`. Returns `None` if there are not enough elements.
+    pub fn select(&self, x: T, k: usize) -> Option<usize> {
+        let x = x.to_discr();
+        let num_superblocks = self.counts.len() / T::NUM_VARIANTS - 1;
+        if k >= self.superblock_count(num_superblocks, x) {
+            return None;
+        }
+
+        // Binary search the last superblock with count <= k
+        let (mut lo, mut hi) = (0, num_superblocks);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.superblock_count(mid, x) <= k {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        // Find the block using the block bithacks
+        let elems_per_block = EnumVec::<T>::ELEMS_PER_BLOCK;
+        let mut remaining = k - self.superblock_count(lo, x);
+        let mut b = lo * SUPERBLOCK_SIZE;
+        loop {
+            let end = ::std::cmp::min((b + 1) * elems_per_block, self.len());
+            let c = self.v.count_raw_between(x, b, end);
+            if remaining < c {
+                break;
+            }
+            remaining -= c;
+            b += 1;
+        }
+
+        // And finally the element
+        let mut i = b * elems_per_block;
+        loop {
+            if unsafe { self.v.get_raw_unchecked(i) } == x {
+                if remaining == 0 {
+                    return Some(i);
+                }
+                remaining -= 1;
+            }
+            i += 1;
+        }
+    }
+
+    /// Returns the number of elements equal to `x`, in O(1) time.
+    pub fn count(&self, x: T) -> usize {
+        self.rank(x, self.len())
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<T> {
+        self.v.get(i)
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.v.len()
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.v.is_empty()
+    }
+
+    /// Returns the memory used by the index, in bytes, not including the
+    /// `EnumVec`.
+    pub fn index_size(&self) -> usize {
+        self.counts.len() * ::std::mem::size_of::<usize>()
+    }
+
+    /// Returns a reference to the inner `EnumVec`.
+    pub fn inner(&self) -> &EnumVec<T> {
+        &self.v
+    }
+
+    /// Drop the index and return the inner `EnumVec`.
+    pub fn into_inner(self) -> EnumVec<T> {
+        self.v
+    }
+}
+
+impl<T: EnumLike> From<EnumVec<T>> for RankSelect<T> {
+    fn from(v: EnumVec<T>) -> Self {
+        Self::new(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_rank_select<T: EnumLike + PartialEq>(v: Vec<T>) {
+        let rs = RankSelect::new(EnumVec::from(v.clone()));
+        for x in 0..T::NUM_VARIANTS {
+            let x = T::from_discr(x);
+            let mut seen = 0;
+            for (i, y) in v.iter().enumerate() {
+                assert_eq!(rs.rank(x, i), seen);
+                if *y == x {
+                    assert_eq!(rs.select(x, seen), Some(i));
+                    seen += 1;
+                }
+            }
+            assert_eq!(rs.rank(x, v.len()), seen);
+            assert_eq!(rs.count(x), seen);
+            assert_eq!(rs.select(x, seen), None);
+        }
+    }
+
+    #[test]
+    fn rank_select_bool() {
+        let v: Vec<_> = (0..5000).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+        check_rank_select(v);
+        check_rank_select::<bool>(vec![]);
+        check_rank_select(vec![true; 2048]);
+    }
+
+    #[test]
+    fn rank_select_small_alphabet() {
+        let v: Vec<_> = (0..3000u32)
+            .map(|i| match i.wrapping_mul(2654435761) % 5 {
+                0 => None,
+                1 => Some(false),
+                _ => Some(true),
+            })
+            .collect();
+        check_rank_select(v);
+    }
+
+    #[test]
+    fn rank_select_many_variants() {
+        // 256 variants, more than the elements per block
+        let v: Vec<_> = (0..2000usize)
+            .map(|i| <[bool; 8]>::from_discr((i * 37) % 256))
+            .collect();
+        check_rank_select(v);
+    }
+}
@@ -0,0 +1,400 @@
+use enum_like::EnumLike;
+use std::fmt;
+use std::iter::FromIterator;
+use vec_u32::EnumVec;
+
+/// A run-length encoded vector of enum variants.
+///
+/// The elements are stored as runs of equal elements: the element of each
+/// run is stored in an `EnumVec`, and the end position of each run in a
+/// `Vec<usize>`, which is used as a prefix index. Therefore `get` is
+/// O(log runs), and `push` and `pop` are O(1). `set` may need to split a run,
+/// which is O(runs) in the worst case.
+///
+/// This is more memory efficient than an `EnumVec` when the elements form
+/// long runs: use `EnumVec::runs().count()` to decide which one to use.
+///
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::RleEnumVec;
+///
+/// let mut ev = EnumVec::from_elem(None, 1000);
+/// ev.set(500, Some(true));
+/// let mut rle = RleEnumVec::from(&ev);
+/// assert_eq!(rle.num_runs(), 3);
+/// assert_eq!(rle.get(500), Some(Some(true)));
+/// rle.set(500, None);
+/// assert_eq!(rle.num_runs(), 1);
+/// rle.set(500, Some(true));
+/// assert_eq!(rle.to_enum_vec(), ev);
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct RleEnumVec<T: EnumLike> {
+    // The element of each run. Two consecutive runs never have the same
+    // element, and there are no empty runs.
+    values: EnumVec<T>,
+    // The end position (exclusive) of each run, ends.last() is the length
+    ends: Vec<usize>,
+}
+
+impl<T: EnumLike> RleEnumVec<T> {
+    /// Creates an empty `RleEnumVec`.
+    pub fn new() -> Self {
+        Self {
+            values: EnumVec::new(),
+            ends: Vec::new(),
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.ends.last().cloned().unwrap_or(0)
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Returns the number of runs.
+    pub fn num_runs(&self) -> usize {
+        self.ends.len()
+    }
+
+    fn run_start(&self, r: usize) -> usize {
+        if r == 0 {
+            0
+        } else {
+            self.ends[r - 1]
+        }
+    }
+
+    // Returns the index of the run which contains the element i
+    fn find_run(&self, i: usize) -> usize {
+        // The first run with end > i
+        match self.ends.binary_search(&i) {
+            Ok(r) => r + 1,
+            Err(r) => r,
+        }
+    }
+
+    /// Returns the element at position `i`, in O(log runs) time.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len() {
+            return None;
+        }
+
+        self.values.get(self.find_run(i))
+    }
+
+    /// Sets the element at position `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`.
+    pub fn set(&mut self, i: usize, x: T) {
+        if i >= self.len() {
+            panic!("index out of bounds: {} >= {}", i, self.len());
+        }
+        let r = self.find_run(i);
+        let old = self.values.get(r).unwrap();
+        if old.to_discr() == x.to_discr() {
+            return;
+        }
+        let start = self.run_start(r);
+        let end = self.ends[r];
+
+        // Split the run into [start, i), [i, i + 1), [i + 1, end)
+        // Skipping the empty parts
+        let mut r = r;
+        if i > start {
+            self.values.insert(r, old);
+            self.ends.insert(r, i);
+            r += 1;
+        }
+        self.values.set(r, x);
+        self.ends[r] = i + 1;
+        if i + 1 < end {
+            self.values.insert(r + 1, old);
+            self.ends.insert(r + 1, end);
+        }
+
+        // Merge with the neighbours, if needed
+        if r + 1 < self.num_runs() && self.same_value(r, r + 1) {
+            self.values.remove(r);
+            self.ends.remove(r);
+        }
+        if r > 0 && self.same_value(r - 1, r) {
+            self.values.remove(r - 1);
+            self.ends.remove(r - 1);
+        }
+    }
+
+    fn same_value(&self, ra: usize, rb: usize) -> bool {
+        unsafe {
+            self.values.get_raw_unchecked(ra)
+                == self.values.get_raw_unchecked(rb)
+        }
+    }
+
+    /// Appends `n` copies of `x` to the end of the vector, in O(1) time.
+    pub fn push_run(&mut self, x: T, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let len = self.len();
+        let extends_last = match self.values.get(self.num_runs().wrapping_sub(1)) {
+            Some(last) => last.to_discr() == x.to_discr(),
+            None => false,
+        };
+        if extends_last {
+            *self.ends.last_mut().unwrap() = len + n;
+        } else {
+            self.values.push(x);
+            self.ends.push(len + n);
+        }
+    }
+
+    /// Appends an element to the end of the vector, in O(1) time.
+    pub fn push(&mut self, x: T) {
+        self.push_run(x, 1);
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        let r = self.num_runs().checked_sub(1)?;
+        let x = self.values.get(r).unwrap();
+        if self.ends[r] - self.run_start(r) == 1 {
+            self.values.pop();
+            self.ends.pop();
+        } else {
+            self.ends[r] -= 1;
+        }
+
+        Some(x)
+    }
+
+    /// Removes all the elements.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.ends.clear();
+    }
+
+    /// Iterator over the elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            v: self,
+            run: 0,
+            idx: 0,
+        }
+    }
+
+    /// Iterator over the runs, as `(element, run length)` pairs.
+    pub fn runs(&self) -> RleRuns<'_, T> {
+        RleRuns { v: self, run: 0 }
+    }
+
+    /// Count the elements equal to `x`.
+    pub fn count(&self, x: T) -> usize {
+        self.runs()
+            .filter(|&(y, _)| y.to_discr() == x.to_discr())
+            .map(|(_, n)| n)
+            .sum()
+    }
+
+    /// Decompress into an `EnumVec`.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        let mut v = EnumVec::with_capacity(self.len());
+        for (x, n) in self.runs() {
+            let len = v.len();
+            v.resize(len + n, x);
+        }
+
+        v
+    }
+
+    /// Copies `self` into a plain `Vec`.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<T: EnumLike> Default for RleEnumVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for RleEnumVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.runs()).finish()
+    }
+}
+
+impl<'a, T: EnumLike> From<&'a EnumVec<T>> for RleEnumVec<T> {
+    fn from(v: &'a EnumVec<T>) -> Self {
+        let mut rle = Self::new();
+        for (x, n) in v.runs() {
+            rle.push_run(x, n);
+        }
+
+        rle
+    }
+}
+
+impl<T: EnumLike> From<EnumVec<T>> for RleEnumVec<T> {
+    fn from(v: EnumVec<T>) -> Self {
+        Self::from(&v)
+    }
+}
+
+impl<T: EnumLike> From<RleEnumVec<T>> for EnumVec<T> {
+    fn from(v: RleEnumVec<T>) -> Self {
+        v.to_enum_vec()
+    }
+}
+
+impl<T: EnumLike> Extend<T> for RleEnumVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+impl<T: EnumLike> FromIterator<T> for RleEnumVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut v = Self::new();
+        v.extend(iter);
+
+        v
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for &'a RleEnumVec<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over &RleEnumVec
+pub struct Iter<'a, T: 'a + EnumLike> {
+    v: &'a RleEnumVec<T>,
+    run: usize,
+    idx: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.v.len() {
+            return None;
+        }
+        if self.idx >= self.v.ends[self.run] {
+            self.run += 1;
+        }
+        self.idx += 1;
+
+        self.v.values.get(self.run)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len() - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+/// Iterator over the runs of a `RleEnumVec`.
+pub struct RleRuns<'a, T: 'a + EnumLike> {
+    v: &'a RleEnumVec<T>,
+    run: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for RleRuns<'a, T> {
+    type Item = (T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let r = self.run;
+        let x = self.v.values.get(r)?;
+        self.run += 1;
+
+        Some((x, self.v.ends[r] - self.v.run_start(r)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.num_runs() - self.run;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for RleRuns<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enum_vec_runs() {
+        // Long runs to test the block skipping
+        let mut v = vec![];
+        for &(x, n) in &[(None, 70), (Some(true), 1), (None, 3), (Some(false), 200)] {
+            for _ in 0..n {
+                v.push(x);
+            }
+        }
+        let ev = EnumVec::from(v.clone());
+        let runs: Vec<_> = ev.runs().collect();
+        assert_eq!(runs, vec![(None, 70), (Some(true), 1), (None, 3), (Some(false), 200)]);
+        assert_eq!(EnumVec::<bool>::new().runs().count(), 0);
+
+        let rle = RleEnumVec::from(&ev);
+        assert_eq!(rle.runs().collect::<Vec<_>>(), runs);
+        assert_eq!(rle.to_vec(), v);
+        assert_eq!(rle.to_enum_vec(), ev);
+    }
+
+    #[test]
+    fn set_get_matches_vec() {
+        let mut v = vec![false; 50];
+        let mut rle: RleEnumVec<_> = v.iter().cloned().collect();
+        let mut x = 12345u32;
+        for _ in 0..500 {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            let i = (x % 50) as usize;
+            let b = x & 0x100 != 0;
+            v[i] = b;
+            rle.set(i, b);
+            assert_eq!(rle.to_vec(), v);
+            // The runs are always merged
+            let ev = EnumVec::from(v.clone());
+            assert_eq!(rle.num_runs(), ev.runs().count());
+        }
+        for (i, b) in v.iter().enumerate() {
+            assert_eq!(rle.get(i), Some(*b));
+        }
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut rle = RleEnumVec::new();
+        rle.push(true);
+        rle.push(true);
+        rle.push(false);
+        assert_eq!(rle.num_runs(), 2);
+        assert_eq!(rle.len(), 3);
+        assert_eq!(rle.pop(), Some(false));
+        assert_eq!(rle.num_runs(), 1);
+        assert_eq!(rle.pop(), Some(true));
+        assert_eq!(rle.pop(), Some(true));
+        assert_eq!(rle.pop(), None);
+        assert!(rle.is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+//! Search for a sequence of elements.
+//!
+//! The elements are never decoded one at a time: whole storage blocks are
+//! shifted into a 128-bit window, and the code of the `q = 64 /
+//! BITS_PER_ELEM` elements at each position is a shift and a mask of that
+//! window, which is compared with the code of the start of the pattern.
+//! Each storage block is read once as the window slides over it. Only when
+//! the codes are equal is the rest of a longer pattern compared, also `q`
+//! elements at a time.
+
+use enum_like::EnumLike;
+use slice::EnumSlice;
+use std::cmp::min;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+// Mask with the lowest n bits set
+fn low_bits(n: usize) -> u128 {
+    if n >= 128 {
+        !0
+    } else {
+        (1 << n) - 1
+    }
+}
+
+// The discriminants of the elements lo..hi of the storage, packed into an
+// integer with element lo in the low bits. Only the elements which may be
+// needed by the next call to code are kept.
+struct Window<'a> {
+    storage: &'a [u32],
+    // Index in storage of the first element of the slice
+    offset: usize,
+    bits: usize,
+    code: u128,
+    lo: usize,
+    hi: usize,
+}
+
+impl<'a> Window<'a> {
+    fn new<T: EnumLike>(s: &EnumSlice<'a, T>) -> Self {
+        let (storage, offset) = s.raw_parts();
+
+        Window {
+            storage,
+            offset,
+            bits: EnumVec::<T>::BITS_PER_ELEM,
+            code: 0,
+            lo: offset,
+            hi: offset,
+        }
+    }
+
+    // Add the elements of the block which contains element hi, starting at
+    // hi
+    fn push_back_block(&mut self) {
+        let epb = packing::elems_per_block(self.bits);
+        let k = self.hi % epb;
+        let block =
+            self.storage[self.hi / epb] as u128 & low_bits(epb * self.bits);
+        self.code |=
+            block >> (k * self.bits) << ((self.hi - self.lo) * self.bits);
+        self.hi += epb - k;
+    }
+
+    // Add the elements of the block which contains element lo - 1, up to
+    // lo - 1
+    fn push_front_block(&mut self) {
+        let epb = packing::elems_per_block(self.bits);
+        let n = (self.lo - 1) % epb + 1;
+        let block =
+            self.storage[(self.lo - 1) / epb] as u128 & low_bits(n * self.bits);
+        self.code = self.code << (n * self.bits) | block;
+        self.lo -= n;
+    }
+
+    // Code of the n elements starting at element i of the slice, with the
+    // first element in the low bits. n * bits must be at most 64.
+    fn code(&mut self, i: usize, n: usize) -> u64 {
+        let (i, end) = (self.offset + i, self.offset + i + n);
+        if i >= self.hi || end <= self.lo {
+            // Nothing to keep
+            self.code = 0;
+            self.lo = i;
+            self.hi = i;
+        }
+        if self.lo < i {
+            // Moving to the right
+            self.code >>= (i - self.lo) * self.bits;
+            self.lo = i;
+        }
+        while self.hi < end {
+            self.push_back_block();
+        }
+        if self.lo > i {
+            // Moving to the left, drop the elements after end so that the
+            // new block fits
+            if self.hi > end {
+                self.code &= low_bits((end - self.lo) * self.bits);
+                self.hi = end;
+            }
+            while self.lo > i {
+                self.push_front_block();
+            }
+        }
+
+        (self.code >> ((i - self.lo) * self.bits) & low_bits(n * self.bits))
+            as u64
+    }
+}
+
+impl<'a, T: EnumLike> EnumSlice<'a, T> {
+    // Number of elements of a pattern of length m which fit in the code
+    fn code_len(m: usize) -> usize {
+        min(m, 64 / EnumVec::<T>::BITS_PER_ELEM)
+    }
+
+    // Compares the elements after the code, the first q elements are known
+    // to be equal
+    fn matches_tail(&self, pattern: &EnumSlice<T>, i: usize, q: usize) -> bool {
+        let mut a = Window::new(self);
+        let mut b = Window::new(pattern);
+        let m = pattern.len();
+
+        (q..m).step_by(q).all(|j| {
+            let n = min(q, m - j);
+            a.code(i + j, n) == b.code(j, n)
+        })
+    }
+
+    // First match which starts at or after start
+    fn find_from(&self, pattern: &EnumSlice<T>, start: usize) -> Option<usize> {
+        let (n, m) = (self.len(), pattern.len());
+        if start > n || m > n - start {
+            return None;
+        }
+        if m == 0 {
+            return Some(start);
+        }
+
+        let q = Self::code_len(m);
+        let target = Window::new(pattern).code(0, q);
+        let mut w = Window::new(self);
+
+        (start..=n - m).find(|&i| {
+            w.code(i, q) == target && self.matches_tail(pattern, i, q)
+        })
+    }
+
+    /// Returns the index of the first occurrence of `pattern`, or `None` if
+    /// it does not appear. An empty pattern matches at index 0.
+    ///
+    /// The pattern can be an `EnumSlice` or a `&EnumVec`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, false, true, false, false].into();
+    /// let pattern: EnumVec<_> = vec![false, true, false].into();
+    /// assert_eq!(ev.find_seq(&pattern), Some(2));
+    /// assert_eq!(ev.slice(3..6).find_seq(&pattern), None);
+    /// ```
+    pub fn find_seq<'p, P>(&self, pattern: P) -> Option<usize>
+    where
+        T: 'p,
+        P: Into<EnumSlice<'p, T>>,
+    {
+        self.find_from(&pattern.into(), 0)
+    }
+
+    /// Returns the index of the last occurrence of `pattern`, or `None` if
+    /// it does not appear. An empty pattern matches at index `self.len()`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, false, true, false, false].into();
+    /// let pattern: EnumVec<_> = vec![true, false].into();
+    /// assert_eq!(ev.rfind_seq(&pattern), Some(3));
+    /// assert_eq!(ev.slice(0..4).rfind_seq(&pattern), Some(0));
+    /// ```
+    pub fn rfind_seq<'p, P>(&self, pattern: P) -> Option<usize>
+    where
+        T: 'p,
+        P: Into<EnumSlice<'p, T>>,
+    {
+        let pattern = pattern.into();
+        let (n, m) = (self.len(), pattern.len());
+        if m > n {
+            return None;
+        }
+        if m == 0 {
+            return Some(n);
+        }
+
+        // The window moves to the left, reading the blocks backwards
+        let q = Self::code_len(m);
+        let target = Window::new(&pattern).code(0, q);
+        let mut w = Window::new(self);
+
+        (0..=n - m).rev().find(|&i| {
+            w.code(i, q) == target && self.matches_tail(&pattern, i, q)
+        })
+    }
+
+    /// Iterator over the start indexes of the non-overlapping occurrences of
+    /// `pattern`, from left to right.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<bool> = "1111011".parse().unwrap();
+    /// let pattern: EnumVec<bool> = "11".parse().unwrap();
+    /// let v: Vec<_> = ev.match_indices(&pattern).collect();
+    /// assert_eq!(v, vec![0, 2, 5]);
+    /// ```
+    pub fn match_indices<'p, P>(&self, pattern: P) -> MatchIndices<'a, 'p, T>
+    where
+        T: 'p,
+        P: Into<EnumSlice<'p, T>>,
+    {
+        MatchIndices {
+            haystack: *self,
+            pattern: pattern.into(),
+            pos: 0,
+        }
+    }
+}
+
+impl<T: EnumLike> EnumVec<T> {
+    /// Returns the index of the first occurrence of `pattern`, see
+    /// `EnumSlice::find_seq`.
+    pub fn find_seq<'p, P>(&self, pattern: P) -> Option<usize>
+    where
+        T: 'p,
+        P: Into<EnumSlice<'p, T>>,
+    {
+        self.as_slice().find_seq(pattern)
+    }
+
+    /// Returns the index of the last occurrence of `pattern`, see
+    /// `EnumSlice::rfind_seq`.
+    pub fn rfind_seq<'p, P>(&self, pattern: P) -> Option<usize>
+    where
+        T: 'p,
+        P: Into<EnumSlice<'p, T>>,
+    {
+        self.as_slice().rfind_seq(pattern)
+    }
+
+    /// Iterator over the start indexes of the non-overlapping occurrences of
+    /// `pattern`, see `EnumSlice::match_indices`.
+    pub fn match_indices<'p, P>(&self, pattern: P) -> MatchIndices<'_, 'p, T>
+    where
+        T: 'p,
+        P: Into<EnumSlice<'p, T>>,
+    {
+        self.as_slice().match_indices(pattern)
+    }
+}
+
+/// Iterator returned by `match_indices`
+pub struct MatchIndices<'a, 'p, T: 'a + 'p + EnumLike> {
+    haystack: EnumSlice<'a, T>,
+    pattern: EnumSlice<'p, T>,
+    // Index where the next search starts
+    pos: usize,
+}
+
+impl<'a, 'p, T: EnumLike> Iterator for MatchIndices<'a, 'p, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let i = self.haystack.find_from(&self.pattern, self.pos)?;
+        // An empty pattern matches at every index
+        self.pos = i + ::std::cmp::max(self.pattern.len(), 1);
+
+        Some(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // All the occurrences, including overlapping ones
+    fn naive<T: EnumLike + PartialEq>(v: &[T], p: &[T]) -> Vec<usize> {
+        if p.len() > v.len() {
+            return vec![];
+        }
+        (0..=v.len() - p.len())
+            .filter(|&i| v[i..i + p.len()] == *p)
+            .collect()
+    }
+
+    fn check<T: EnumLike + PartialEq + ::std::fmt::Debug>(v: &[T]) {
+        let ev = EnumVec::from(v.to_vec());
+        for &(start, m) in &[(0, 1), (5, 3), (17, 8), (40, 21), (3, 70), (9, 0)]
+        {
+            let p = &v[start..start + m];
+            let pattern = EnumVec::from(p.to_vec());
+            let expected = naive(v, p);
+            assert_eq!(ev.find_seq(&pattern), expected.first().cloned());
+            assert_eq!(ev.rfind_seq(&pattern), expected.last().cloned());
+            let s = ev.slice(2..v.len() - 1);
+            let expected = naive(&v[2..v.len() - 1], p);
+            assert_eq!(s.find_seq(&pattern), expected.first().cloned());
+            assert_eq!(
+                s.rfind_seq(pattern.slice(0..m)),
+                expected.last().cloned()
+            );
+        }
+    }
+
+    #[test]
+    fn find_matches_naive() {
+        let v: Vec<_> = (0..300usize).map(|i| i * i % 7 < 3).collect();
+        check(&v);
+        let v: Vec<_> = (0..300usize)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i * i % 5))
+            .collect();
+        check(&v);
+        let v: Vec<_> = (0..300usize)
+            .map(|i| <[Option<bool>; 5]>::from_discr(i * i % 243))
+            .collect();
+        check(&v);
+    }
+
+    #[test]
+    fn window_codes() {
+        // 3 bits per element, with 2 unused bits in each block
+        let v: Vec<_> = (0..300usize)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i * i % 5))
+            .collect();
+        let ev = EnumVec::from(v.clone());
+        let s = ev.slice(7..300);
+        let expected = |i: usize, n: usize| {
+            (0..n).fold(0, |code, j| {
+                code | (v[7 + i + j].to_discr() as u64) << (3 * j)
+            })
+        };
+        let mut w = Window::new(&s);
+        for i in (0..=280).chain((0..=280).rev()).chain((0..280).step_by(13)) {
+            assert_eq!(w.code(i, 13), expected(i, 13), "at {}", i);
+        }
+        assert_eq!(w.code(292, 1), expected(292, 1));
+    }
+
+    #[test]
+    fn match_indices_non_overlapping() {
+        let ev: EnumVec<bool> = "0000010000".parse().unwrap();
+        let p: EnumVec<bool> = "00".parse().unwrap();
+        let v: Vec<_> = ev.match_indices(&p).collect();
+        assert_eq!(v, vec![0, 2, 6, 8]);
+        let v: Vec<_> = ev.slice(1..5).match_indices(&p).collect();
+        assert_eq!(v, vec![0, 2]);
+
+        let empty = EnumVec::<bool>::new();
+        let v: Vec<_> = ev.slice(0..3).match_indices(&empty).collect();
+        assert_eq!(v, vec![0, 1, 2, 3]);
+        assert_eq!(ev.rfind_seq(&empty), Some(10));
+        assert_eq!(empty.find_seq(&p), None);
+    }
+}
@@ -0,0 +1,258 @@
+use enum_like::{EnumLike, EnumTable};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Index, Range};
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+/// A view of a range of elements of an `EnumVec`, without copying them.
+///
+/// ```
+/// use enum_vec::EnumVec;
+///
+/// let ev: EnumVec<_> = vec![true, false, false, true, true].into();
+/// let s = ev.slice(1..4);
+/// assert_eq!(s.len(), 3);
+/// assert_eq!(s.get(2), Some(true));
+/// assert_eq!(s.slice(1..3).to_vec(), vec![false, true]);
+/// ```
+pub struct EnumSlice<'a, T: 'a + EnumLike> {
+    storage: &'a [u32],
+    // Index in storage of the first element
+    start: usize,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+// Derive would require T: Copy
+impl<'a, T: EnumLike> Clone for EnumSlice<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T: EnumLike> Copy for EnumSlice<'a, T> {}
+
+impl<T: EnumLike> EnumVec<T> {
+    /// Returns a slice containing all the elements.
+    pub fn as_slice(&self) -> EnumSlice<'_, T> {
+        EnumSlice {
+            storage: self.storage(),
+            start: 0,
+            len: self.len(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns a slice of the elements in `range`.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> EnumSlice<'_, T> {
+        self.as_slice().slice(range)
+    }
+}
+
+impl<'a, T: EnumLike> EnumSlice<'a, T> {
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a slice of the elements in `range`, relative to this slice.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> EnumSlice<'a, T> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range out of bounds: {:?} with length {}",
+            range,
+            self.len
+        );
+
+        EnumSlice {
+            storage: self.storage,
+            start: self.start + range.start,
+            len: range.end - range.start,
+            phantom: PhantomData,
+        }
+    }
+
+    // The storage blocks which hold the slice, and the index in them of the
+    // first element
+    pub(crate) fn raw_parts(&self) -> (&'a [u32], usize) {
+        (self.storage, self.start)
+    }
+
+    // The discriminant of element i, which must be in bounds
+    pub(crate) fn discr_at(&self, i: usize) -> usize {
+        debug_assert!(i < self.len);
+        let bits = EnumVec::<T>::BITS_PER_ELEM;
+        unsafe { packing::get_unchecked(self.storage, bits, self.start + i) }
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+
+        Some(T::from_discr(self.discr_at(i)))
+    }
+
+    /// Iterator over the elements.
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter {
+            s: *self,
+            front: 0,
+            back: self.len,
+        }
+    }
+
+    /// Copies the elements into a new `EnumVec`.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        self.iter().collect()
+    }
+
+    /// Copies the elements into a plain `Vec`.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<'a, T: EnumLike> From<&'a EnumVec<T>> for EnumSlice<'a, T> {
+    fn from(v: &'a EnumVec<T>) -> Self {
+        v.as_slice()
+    }
+}
+
+impl<'a, T: EnumLike> From<EnumSlice<'a, T>> for EnumVec<T> {
+    fn from(s: EnumSlice<'a, T>) -> Self {
+        s.to_enum_vec()
+    }
+}
+
+impl<'a, T: EnumLike + fmt::Debug> fmt::Debug for EnumSlice<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, 'b, T: EnumLike> PartialEq<EnumSlice<'b, T>> for EnumSlice<'a, T> {
+    fn eq(&self, other: &EnumSlice<'b, T>) -> bool {
+        self.len == other.len
+            && (0..self.len).all(|i| self.discr_at(i) == other.discr_at(i))
+    }
+}
+
+impl<'a, T: EnumLike> Eq for EnumSlice<'a, T> {}
+
+impl<'a, T: EnumTable> Index<usize> for EnumSlice<'a, T> {
+    type Output = T;
+
+    /// Returns a reference to the value in `T::ALL` with the discriminant
+    /// of the element at position `i`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, true].into();
+    /// let s = ev.slice(1..3);
+    /// assert_eq!((s[0], s[1]), (false, true));
+    /// ```
+    fn index(&self, i: usize) -> &T {
+        if i >= self.len {
+            panic!("index out of bounds: {} >= {}", i, self.len);
+        }
+
+        &T::ALL[self.discr_at(i)]
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for EnumSlice<'a, T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over EnumSlice
+pub struct Iter<'a, T: 'a + EnumLike> {
+    s: EnumSlice<'a, T>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let x = T::from_discr(self.s.discr_at(self.front));
+        self.front += 1;
+
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+
+        Some(T::from_discr(self.s.discr_at(self.back)))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_slices() {
+        let v: Vec<_> = (0..200usize)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i * 7 % 5))
+            .collect();
+        let ev = EnumVec::from(v.clone());
+        let s = ev.slice(13..150);
+        assert_eq!(s.to_vec(), v[13..150].to_vec());
+        let t = s.slice(40..100);
+        assert_eq!(t.to_vec(), v[53..113].to_vec());
+        assert_eq!(t.iter().rev().collect::<Vec<_>>(), {
+            let mut r = v[53..113].to_vec();
+            r.reverse();
+            r
+        });
+        assert_eq!(t.get(60), None);
+        assert_eq!(ev.slice(10..20), ev.slice(15..25));
+        assert!(ev.slice(3..3).is_empty());
+        assert_eq!(EnumVec::from(t), v[53..113].to_vec().into());
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_bounds() {
+        let ev: EnumVec<_> = vec![true; 10].into();
+        ev.slice(2..5).slice(0..4);
+    }
+}
@@ -0,0 +1,351 @@
+use enum_like::EnumLike;
+use std::fmt;
+use std::iter::Zip;
+use std::slice;
+use vec_u32::{EnumVec, EnumVecIter};
+
+/// A vector where most of the elements are equal to a default value.
+///
+/// Only the non-default elements are stored: their positions in a sorted
+/// `Vec<usize>`, and their values in an `EnumVec`. So `get` and `set` take
+/// O(log n) time, where n is the number of non-default elements, and `set`
+/// may need to shift the positions, which is O(n) in the worst case.
+///
+/// ```
+/// use enum_vec::SparseEnumVec;
+///
+/// let mut sv = SparseEnumVec::from_elem(None, 1_000_000);
+/// sv.set(10, Some(true));
+/// sv.set(999_999, Some(false));
+/// assert_eq!(sv.get(10), Some(Some(true)));
+/// assert_eq!(sv.get(11), Some(None));
+/// assert_eq!(sv.count(None), 999_998);
+/// let non_default: Vec<_> = sv.iter_non_default().collect();
+/// assert_eq!(non_default, vec![(10, Some(true)), (999_999, Some(false))]);
+/// ```
+#[derive(Clone)]
+pub struct SparseEnumVec<T: EnumLike> {
+    default: T,
+    len: usize,
+    // Sorted positions of the non-default elements
+    positions: Vec<usize>,
+    // values[k] is the element at positions[k]
+    values: EnumVec<T>,
+}
+
+impl<T: EnumLike> SparseEnumVec<T> {
+    /// Creates an empty vector with the given default value.
+    pub fn new(default: T) -> Self {
+        Self::from_elem(default, 0)
+    }
+
+    /// Creates a vector with `n` copies of the default value. This does not
+    /// allocate.
+    pub fn from_elem(default: T, n: usize) -> Self {
+        Self {
+            default,
+            len: n,
+            positions: Vec::new(),
+            values: EnumVec::new(),
+        }
+    }
+
+    /// Copies the elements of `v`, using `default` as the default value.
+    pub fn from_enum_vec(v: &EnumVec<T>, default: T) -> Self {
+        let mut sv = Self::new(default);
+        for (x, n) in v.runs() {
+            if Self::is_default(default, x) {
+                sv.len += n;
+            } else {
+                for _ in 0..n {
+                    sv.push(x);
+                }
+            }
+        }
+
+        sv
+    }
+
+    /// Returns the default value.
+    pub fn default_value(&self) -> T {
+        self.default
+    }
+
+    fn is_default(default: T, x: T) -> bool {
+        default.to_discr() == x.to_discr()
+    }
+
+    /// Returns the number of elements, including the default ones.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements not equal to the default value.
+    pub fn num_non_default(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+        match self.positions.binary_search(&i) {
+            Ok(k) => self.values.get(k),
+            Err(_) => Some(self.default),
+        }
+    }
+
+    /// Sets the element at position `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= self.len()`.
+    pub fn set(&mut self, i: usize, x: T) {
+        if i >= self.len {
+            panic!("index out of bounds: {} >= {}", i, self.len);
+        }
+        let found = self.positions.binary_search(&i);
+        match (found, Self::is_default(self.default, x)) {
+            (Ok(k), true) => {
+                self.positions.remove(k);
+                self.values.remove(k);
+            }
+            (Ok(k), false) => self.values.set(k, x),
+            (Err(k), false) => {
+                self.positions.insert(k, i);
+                self.values.insert(k, x);
+            }
+            (Err(_), true) => {}
+        }
+    }
+
+    /// Appends an element to the end of the vector.
+    pub fn push(&mut self, x: T) {
+        if !Self::is_default(self.default, x) {
+            self.positions.push(self.len);
+            self.values.push(x);
+        }
+        self.len += 1;
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        if self.positions.last() == Some(&self.len) {
+            self.positions.pop();
+            self.values.pop()
+        } else {
+            Some(self.default)
+        }
+    }
+
+    /// Changes the length of the vector, filling the new elements with the
+    /// default value.
+    pub fn resize(&mut self, new_len: usize) {
+        if new_len < self.len {
+            let k = match self.positions.binary_search(&new_len) {
+                Ok(k) | Err(k) => k,
+            };
+            self.positions.truncate(k);
+            self.values.truncate(k);
+        }
+        self.len = new_len;
+    }
+
+    /// Removes all the elements.
+    pub fn clear(&mut self) {
+        self.resize(0);
+    }
+
+    /// Count the elements equal to `x`.
+    pub fn count(&self, x: T) -> usize {
+        if Self::is_default(self.default, x) {
+            self.len - self.positions.len()
+        } else {
+            self.values.count(x)
+        }
+    }
+
+    /// Check whether any of the elements is equal to `x`.
+    pub fn any(&self, x: T) -> bool {
+        if Self::is_default(self.default, x) {
+            self.positions.len() < self.len
+        } else {
+            self.values.any(x)
+        }
+    }
+
+    /// Check whether all of the elements are equal to `x`.
+    pub fn all(&self, x: T) -> bool {
+        if Self::is_default(self.default, x) {
+            self.positions.is_empty()
+        } else {
+            self.positions.len() == self.len && self.values.all(x)
+        }
+    }
+
+    /// Iterator over all the elements, including the default ones.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            v: self,
+            idx: 0,
+            next_k: 0,
+        }
+    }
+
+    /// Iterator over the non-default elements, as `(position, element)`
+    /// pairs, in ascending order.
+    pub fn iter_non_default(&self) -> NonDefault<'_, T> {
+        self.positions.iter().cloned().zip(self.values.iter())
+    }
+
+    /// Converts into an `EnumVec`.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        let mut v = EnumVec::from_elem(self.default, self.len);
+        for (i, x) in self.iter_non_default() {
+            v.set(i, x);
+        }
+
+        v
+    }
+
+    /// Copies `self` into a plain `Vec`.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<T: EnumLike + fmt::Debug> fmt::Debug for SparseEnumVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: EnumLike> PartialEq for SparseEnumVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().zip(other.iter()).all(|(a, b)| {
+            a.to_discr() == b.to_discr()
+        })
+    }
+}
+
+impl<T: EnumLike> Eq for SparseEnumVec<T> {}
+
+impl<T: EnumLike> From<SparseEnumVec<T>> for EnumVec<T> {
+    fn from(v: SparseEnumVec<T>) -> Self {
+        v.to_enum_vec()
+    }
+}
+
+impl<T: EnumLike> Extend<T> for SparseEnumVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for &'a SparseEnumVec<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the non-default elements of a `SparseEnumVec`.
+pub type NonDefault<'a, T> =
+    Zip<::std::iter::Cloned<slice::Iter<'a, usize>>, EnumVecIter<'a, T>>;
+
+/// Iterator over &SparseEnumVec
+pub struct Iter<'a, T: 'a + EnumLike> {
+    v: &'a SparseEnumVec<T>,
+    idx: usize,
+    // Index of the next non-default element
+    next_k: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.v.len {
+            return None;
+        }
+        let i = self.idx;
+        self.idx += 1;
+        if self.v.positions.get(self.next_k) == Some(&i) {
+            self.next_k += 1;
+            self.v.values.get(self.next_k - 1)
+        } else {
+            Some(self.v.default)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let mut ev = EnumVec::from_elem(None, 300);
+        ev.set(0, Some(false));
+        ev.set(150, Some(true));
+        ev.set(151, Some(true));
+        ev.push(Some(false));
+        let sv = SparseEnumVec::from_enum_vec(&ev, None);
+        assert_eq!(sv.len(), 301);
+        assert_eq!(sv.num_non_default(), 4);
+        assert_eq!(sv.to_enum_vec(), ev);
+        assert_eq!(sv.to_vec(), ev.to_vec());
+        assert_eq!(sv.count(Some(true)), 2);
+        assert_eq!(sv.count(None), 297);
+        assert!(sv.any(Some(false)));
+        assert!(!sv.all(None));
+
+        // A different default value
+        let sv = SparseEnumVec::from_enum_vec(&ev, Some(true));
+        assert_eq!(sv.num_non_default(), 299);
+        assert_eq!(EnumVec::from(sv), ev);
+    }
+
+    #[test]
+    fn set_push_pop() {
+        let mut sv = SparseEnumVec::from_elem(false, 10);
+        sv.set(3, true);
+        sv.set(7, true);
+        sv.set(5, true);
+        assert_eq!(sv.iter_non_default().map(|(i, _)| i).collect::<Vec<_>>(),
+                   vec![3, 5, 7]);
+        sv.set(5, false);
+        assert_eq!(sv.num_non_default(), 2);
+        sv.push(true);
+        assert_eq!(sv.pop(), Some(true));
+        assert_eq!(sv.pop(), Some(false));
+        sv.resize(5);
+        assert_eq!(sv.to_vec(), vec![false, false, false, true, false]);
+        sv.resize(6);
+        assert_eq!(sv.count(false), 5);
+        sv.clear();
+        assert!(sv.is_empty());
+        assert!(sv.all(true));
+    }
+}
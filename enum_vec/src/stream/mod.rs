@@ -0,0 +1,455 @@
+use enum_like::EnumLike;
+use packed::{self, first_invalid, FormatError, Header};
+use packed::{BLOCK_BYTES, HEADER_LEN};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+/// Writes elements in the packed format one at a time, without keeping them
+/// in memory.
+///
+/// Each block is written to `W` as soon as it is full, so it is recommended
+/// to use a `BufWriter`. When the number of elements is known in advance,
+/// use `with_len` and the output will be the same as `EnumVec::write_packed`.
+/// Otherwise use `new`, which writes the length at the end, see the `packed`
+/// module.
+///
+/// Call `finish` after writing the last element. As with `BufWriter`, the
+/// `EnumWriter` is also finished when dropped, ignoring any errors.
+///
+/// ```
+/// use enum_vec::{EnumReader, EnumWriter};
+///
+/// let mut w = EnumWriter::new(vec![]).unwrap();
+/// for i in 0..100 {
+///     w.write(Some(i % 3 == 0)).unwrap();
+/// }
+/// let buf = w.finish().unwrap();
+///
+/// let r = EnumReader::<_, Option<bool>>::new(&buf[..]).unwrap();
+/// let v: Vec<_> = r.collect::<Result<_, _>>().unwrap();
+/// assert_eq!(v.len(), 100);
+/// assert_eq!(v[3], Some(true));
+/// ```
+pub struct EnumWriter<W: Write, T: EnumLike> {
+    // None after finishing
+    w: Option<W>,
+    // The length written in the header, or None when streaming
+    len: Option<usize>,
+    written: usize,
+    block: u32,
+    phantom: PhantomData<T>,
+}
+
+impl<W: Write, T: EnumLike> EnumWriter<W, T> {
+    const BITS: usize = EnumVec::<T>::BITS_PER_ELEM;
+    const ELEMS_PER_BLOCK: usize = EnumVec::<T>::ELEMS_PER_BLOCK;
+
+    /// Creates a writer for an unknown number of elements, and writes the
+    /// header.
+    pub fn new(w: W) -> io::Result<Self> {
+        Self::with_header(w, None)
+    }
+
+    /// Creates a writer for exactly `len` elements, and writes the header.
+    /// The output is the same as `EnumVec::write_packed`.
+    pub fn with_len(w: W, len: usize) -> io::Result<Self> {
+        Self::with_header(w, Some(len))
+    }
+
+    fn with_header(mut w: W, len: Option<usize>) -> io::Result<Self> {
+        let mut header = Header::new::<T>(len.unwrap_or(0));
+        header.stream = len.is_none();
+        w.write_all(&header.to_bytes())?;
+
+        Ok(Self {
+            w: Some(w),
+            len,
+            written: 0,
+            block: 0,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the number of elements written.
+    pub fn len(&self) -> usize {
+        self.written
+    }
+
+    /// Returns `true` if no elements were written.
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// Writes an element. Returns an error if the writer was created using
+    /// `with_len` and all the elements were already written.
+    pub fn write(&mut self, x: T) -> io::Result<()> {
+        if Some(self.written) == self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "more elements than the length in the header",
+            ));
+        }
+        let k = self.written % Self::ELEMS_PER_BLOCK;
+        self.block |= (x.to_discr() as u32) << (k * Self::BITS);
+        self.written += 1;
+        if k + 1 == Self::ELEMS_PER_BLOCK {
+            self.write_block()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let block = self.block;
+        self.block = 0;
+
+        self.w.as_mut().unwrap().write_all(&block.to_le_bytes())
+    }
+
+    /// Writes the last block and, when streaming, the length. Returns the
+    /// inner writer.
+    ///
+    /// Returns an error if the writer was created using `with_len` and the
+    /// number of elements written is different.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_inner()
+    }
+
+    fn finish_inner(&mut self) -> io::Result<W> {
+        // Taken first, so that `Drop` does not try again after an error
+        let mut w = self.w.take().unwrap();
+        if self.written % Self::ELEMS_PER_BLOCK != 0 {
+            w.write_all(&self.block.to_le_bytes())?;
+        }
+        match self.len {
+            None => w.write_all(&(self.written as u64).to_le_bytes())?,
+            Some(len) if len != self.written => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("expected {} elements, got {}", len, self.written),
+                ));
+            }
+            Some(_) => {}
+        }
+        w.flush()?;
+
+        Ok(w)
+    }
+}
+
+impl<W: Write, T: EnumLike> Drop for EnumWriter<W, T> {
+    fn drop(&mut self) {
+        if self.w.is_some() {
+            let _r = self.finish_inner();
+        }
+    }
+}
+
+/// Reads elements in the packed format one at a time, without loading them
+/// in memory.
+///
+/// Reads both the output of `EnumVec::write_packed` and `EnumWriter`. Each
+/// block is checked when it is read, so an invalid discriminant is only
+/// reported when the iterator reaches its block. After the first error the
+/// iterator returns `None`.
+///
+/// ```
+/// use enum_vec::{EnumReader, EnumVec};
+///
+/// let ev: EnumVec<_> = vec![true, false, true].into();
+/// let mut buf = vec![];
+/// ev.write_packed(&mut buf).unwrap();
+///
+/// let mut r = EnumReader::<_, bool>::new(&buf[..]).unwrap();
+/// assert_eq!(r.total_len(), Some(3));
+/// assert_eq!(r.next().unwrap().unwrap(), true);
+/// assert_eq!(r.filter(|x| *x.as_ref().unwrap()).count(), 1);
+/// ```
+pub struct EnumReader<R: Read, T: EnumLike> {
+    r: R,
+    // Known from the start, or after reading the last block when streaming
+    len: Option<usize>,
+    idx: usize,
+    block: u32,
+    // When streaming, the next block followed by 8 more bytes. The last 8
+    // bytes of the input are the length.
+    ahead: [u8; BLOCK_BYTES + 8],
+    done: bool,
+    phantom: PhantomData<T>,
+}
+
+impl<R: Read, T: EnumLike> EnumReader<R, T> {
+    const BITS: usize = EnumVec::<T>::BITS_PER_ELEM;
+    const ELEMS_PER_BLOCK: usize = EnumVec::<T>::ELEMS_PER_BLOCK;
+
+    /// Reads the header and checks that the elements can be read as `T`.
+    pub fn new(mut r: R) -> io::Result<Self> {
+        let mut header = [0; HEADER_LEN];
+        packed::read_exact_or_truncated(&mut r, &mut header)?;
+        let header = Header::from_bytes(&header)?;
+
+        Self::with_header(r, header)
+    }
+
+    pub(crate) fn with_header(mut r: R, header: Header) -> io::Result<Self> {
+        header.check::<T>()?;
+        let mut ahead = [0; BLOCK_BYTES + 8];
+        let len = if header.is_stream() {
+            packed::read_exact_or_truncated(&mut r, &mut ahead[..8])?;
+            if read_block_or_end(&mut r, &mut ahead[8..])? {
+                None
+            } else {
+                // No blocks, only the length
+                match footer_len(&ahead[..8])? {
+                    0 => Some(0),
+                    _ => return Err(FormatError::BadHeader.into()),
+                }
+            }
+        } else {
+            Some(header.len as usize)
+        };
+
+        Ok(Self {
+            r,
+            len,
+            idx: 0,
+            block: 0,
+            ahead,
+            done: false,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the total number of elements, if it is known. When reading
+    /// the output of `EnumWriter::new`, it is only known after reading the
+    /// last block.
+    pub fn total_len(&self) -> Option<usize> {
+        self.len
+    }
+
+    /// Returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    fn read_block(&mut self) -> io::Result<()> {
+        let n = match self.len {
+            Some(len) => {
+                let mut block = [0; BLOCK_BYTES];
+                packed::read_exact_or_truncated(&mut self.r, &mut block)?;
+                self.block = u32::from_le_bytes(block);
+                ::std::cmp::min(Self::ELEMS_PER_BLOCK, len - self.idx)
+            }
+            None => {
+                let a = self.ahead;
+                self.block = u32::from_le_bytes([a[0], a[1], a[2], a[3]]);
+                self.ahead.copy_within(BLOCK_BYTES.., 0);
+                if read_block_or_end(&mut self.r, &mut self.ahead[8..])? {
+                    Self::ELEMS_PER_BLOCK
+                } else {
+                    // This was the last block
+                    let len = footer_len(&self.ahead[..8])?;
+                    if len <= self.idx || len - self.idx > Self::ELEMS_PER_BLOCK {
+                        return Err(FormatError::BadHeader.into());
+                    }
+                    self.len = Some(len);
+                    len - self.idx
+                }
+            }
+        };
+        if let Some(k) = first_invalid::<T>(self.block, n) {
+            return Err(FormatError::InvalidDiscriminant(self.idx + k).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read, T: EnumLike> Iterator for EnumReader<R, T> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || Some(self.idx) == self.len {
+            return None;
+        }
+        if self.idx % Self::ELEMS_PER_BLOCK == 0 {
+            if let Err(e) = self.read_block() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        let shift = (self.idx % Self::ELEMS_PER_BLOCK) * Self::BITS;
+        let x = (self.block >> shift) & packing::element_mask(Self::BITS);
+        self.idx += 1;
+
+        Some(Ok(T::from_discr(x as usize)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.len {
+            _ if self.done => (0, Some(0)),
+            Some(len) => (len - self.idx, Some(len - self.idx)),
+            None => (0, None),
+        }
+    }
+}
+
+// Fill buf, returning false if the input ends before the first byte
+fn read_block_or_end<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(FormatError::Truncated.into()),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
+
+fn footer_len(b: &[u8]) -> Result<usize, FormatError> {
+    let mut len = [0; 8];
+    len.copy_from_slice(b);
+    let len = u64::from_le_bytes(len);
+    if len > usize::MAX as u64 {
+        return Err(FormatError::BadHeader);
+    }
+
+    Ok(len as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type X = Option<(bool, bool)>;
+
+    fn elements(n: usize) -> Vec<X> {
+        (0..n).map(|i| X::from_discr(i * 7 % 5)).collect()
+    }
+
+    fn read_all(buf: &[u8]) -> io::Result<Vec<X>> {
+        EnumReader::new(buf)?.collect()
+    }
+
+    #[test]
+    fn same_as_write_packed() {
+        for &n in &[0, 1, 9, 10, 11, 1000] {
+            let v = elements(n);
+            let ev = EnumVec::from(v.clone());
+            let mut expected = vec![];
+            ev.write_packed(&mut expected).unwrap();
+
+            let mut w = EnumWriter::with_len(vec![], n).unwrap();
+            for &x in &v {
+                w.write(x).unwrap();
+            }
+            assert!(w.write(None).is_err());
+            let buf = w.finish().unwrap();
+            assert_eq!(buf, expected);
+            assert_eq!(read_all(&buf).unwrap(), v);
+        }
+
+        let mut w = EnumWriter::with_len(vec![], 2).unwrap();
+        w.write(true).unwrap();
+        assert!(w.finish().is_err());
+    }
+
+    #[test]
+    fn streaming() {
+        // 3 bits per element, 10 elements per block
+        for &n in &[0, 1, 9, 10, 11, 1000] {
+            let v = elements(n);
+            let mut w = EnumWriter::new(vec![]).unwrap();
+            for &x in &v {
+                w.write(x).unwrap();
+            }
+            let buf = w.finish().unwrap();
+            assert_eq!(buf[7], packed::FLAG_STREAM);
+            let blocks = (n + 9) / 10;
+            assert_eq!(buf.len(), HEADER_LEN + blocks * BLOCK_BYTES + 8);
+
+            let mut r = EnumReader::<_, X>::new(&buf[..]).unwrap();
+            assert_eq!(r.total_len(), if n == 0 { Some(0) } else { None });
+            assert_eq!(r.by_ref().collect::<io::Result<Vec<_>>>().unwrap(), v);
+            assert_eq!(r.total_len(), Some(n));
+            assert_eq!(EnumVec::<X>::read_packed(&buf[..]).unwrap().to_vec(), v);
+        }
+
+        // Dropping the writer also writes the length
+        let mut buf = vec![];
+        {
+            let mut w = EnumWriter::<_, X>::new(&mut buf).unwrap();
+            w.write(None).unwrap();
+        }
+        assert_eq!(read_all(&buf).unwrap(), vec![None]);
+    }
+
+    // Accepts the header, then fails and counts the failed writes
+    struct FailAfterHeader<'a> {
+        written: usize,
+        failures: &'a ::std::cell::Cell<usize>,
+    }
+
+    impl<'a> Write for FailAfterHeader<'a> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written >= HEADER_LEN {
+                // `write_all` turns this into an error
+                self.failures.set(self.failures.get() + 1);
+                return Ok(0);
+            }
+            let n = ::std::cmp::min(buf.len(), HEADER_LEN - self.written);
+            self.written += n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finish_error() {
+        let failures = ::std::cell::Cell::new(0);
+        let w = FailAfterHeader {
+            written: 0,
+            failures: &failures,
+        };
+        let mut w = EnumWriter::new(w).unwrap();
+        w.write(true).unwrap();
+        assert!(w.finish().is_err());
+        // Dropping the writer does not write the last block again
+        assert_eq!(failures.get(), 1);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let mut w = EnumWriter::new(vec![]).unwrap();
+        for x in elements(25) {
+            w.write(x).unwrap();
+        }
+        let buf = w.finish().unwrap();
+        let err = |buf: &[u8]| {
+            let e = read_all(buf).unwrap_err();
+            *e.into_inner().unwrap().downcast::<FormatError>().unwrap()
+        };
+        assert_eq!(err(&buf[..buf.len() - 1]), FormatError::Truncated);
+        // The length does not match the number of blocks
+        let mut bad = buf.clone();
+        let n = bad.len();
+        bad[n - 8] = 35;
+        assert_eq!(err(&bad), FormatError::BadHeader);
+        // Set element 21 to the invalid discriminant 7
+        let mut bad = buf.clone();
+        bad[HEADER_LEN + 2 * BLOCK_BYTES] |= 0b111 << 3;
+        assert_eq!(err(&bad), FormatError::InvalidDiscriminant(21));
+        // The elements of the previous blocks are returned
+        let r = EnumReader::<_, X>::new(&bad[..]).unwrap();
+        assert_eq!(r.take_while(|x| x.is_ok()).count(), 20);
+    }
+}
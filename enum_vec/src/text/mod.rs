@@ -0,0 +1,255 @@
+use enum_like::{EnumLike, EnumSymbol};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use vec_u32::EnumVec;
+
+// Marks the entries of DecodeTable which are not a symbol
+const INVALID: u32 = !0;
+
+// Maps each char below 256 to the discriminant with that symbol
+struct DecodeTable {
+    table: [u32; 256],
+    // All the symbols are ASCII, so the input can be decoded byte by byte
+    ascii: bool,
+    // Some symbols are not in the table
+    wide: bool,
+}
+
+impl DecodeTable {
+    fn new<T: EnumSymbol>() -> Self {
+        let mut t = Self {
+            table: [INVALID; 256],
+            ascii: true,
+            wide: false,
+        };
+        for d in 0..T::NUM_VARIANTS {
+            let c = T::from_discr(d).symbol() as usize;
+            t.ascii &= c < 128;
+            if c < 256 {
+                t.table[c] = d as u32;
+            } else {
+                t.wide = true;
+            }
+        }
+
+        t
+    }
+
+    fn decode<T: EnumSymbol>(&self, c: char) -> Option<T> {
+        match self.table.get(c as usize) {
+            Some(&d) if d != INVALID => Some(T::from_discr(d as usize)),
+            Some(_) => None,
+            None if self.wide => {
+                (0..T::NUM_VARIANTS)
+                    .map(T::from_discr)
+                    .find(|x| x.symbol() == c)
+            }
+            None => None,
+        }
+    }
+}
+
+// Maps each discriminant to its symbol, if there are at most 256 values
+fn encode_table<T: EnumSymbol>() -> Option<[char; 256]> {
+    if T::NUM_VARIANTS > 256 {
+        return None;
+    }
+    let mut table = ['\0'; 256];
+    for (d, c) in table.iter_mut().enumerate().take(T::NUM_VARIANTS) {
+        *c = T::from_discr(d).symbol();
+    }
+
+    Some(table)
+}
+
+/// Error returned when parsing an `EnumVec` from a string which contains an
+/// invalid symbol.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseEnumVecError {
+    position: usize,
+    symbol: char,
+}
+
+impl ParseEnumVecError {
+    /// Position of the invalid symbol, counted in characters, not bytes.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The invalid symbol.
+    pub fn symbol(&self) -> char {
+        self.symbol
+    }
+}
+
+impl fmt::Display for ParseEnumVecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid symbol {:?} at position {}",
+            self.symbol, self.position
+        )
+    }
+}
+
+impl Error for ParseEnumVecError {}
+
+impl<T: EnumLike> EnumVec<T> {
+    /// Parses a string, using `f` to convert each character into an
+    /// element.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev = EnumVec::from_str_with("+-+", |c| match c {
+    ///     '+' => Some(true),
+    ///     '-' => Some(false),
+    ///     _ => None,
+    /// }).unwrap();
+    /// assert_eq!(ev.to_vec(), vec![true, false, true]);
+    ///
+    /// let e = EnumVec::from_str_with("+-x", |_| None::<bool>).unwrap_err();
+    /// assert_eq!(e.position(), 0);
+    /// ```
+    pub fn from_str_with<F>(s: &str, mut f: F) -> Result<Self, ParseEnumVecError>
+    where
+        F: FnMut(char) -> Option<T>,
+    {
+        let mut v = Self::with_capacity(s.len());
+        for (position, c) in s.chars().enumerate() {
+            match f(c) {
+                Some(x) => v.push(x),
+                None => {
+                    return Err(ParseEnumVecError {
+                        position,
+                        symbol: c,
+                    })
+                }
+            }
+        }
+
+        Ok(v)
+    }
+
+    /// Converts into a string, using `f` to convert each element into a
+    /// character.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, true].into();
+    /// assert_eq!(ev.to_string_with(|x| if x { '+' } else { '-' }), "+-+");
+    /// ```
+    pub fn to_string_with<F>(&self, f: F) -> String
+    where
+        F: FnMut(T) -> char,
+    {
+        self.iter().map(f).collect()
+    }
+}
+
+impl<T: EnumSymbol> fmt::Display for EnumVec<T> {
+    /// Writes the symbol of each element.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, true].into();
+    /// assert_eq!(ev.to_string(), "101");
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match encode_table::<T>() {
+            Some(table) => self.to_string_with(|x| table[x.to_discr()]),
+            None => self.to_string_with(T::symbol),
+        };
+
+        f.write_str(&s)
+    }
+}
+
+impl<T: EnumSymbol> FromStr for EnumVec<T> {
+    type Err = ParseEnumVecError;
+
+    /// Parses a string where each character is the symbol of one element.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let ev: EnumVec<bool> = "1101".parse().unwrap();
+    /// assert_eq!(ev.count(true), 3);
+    /// let e = "1121".parse::<EnumVec<bool>>().unwrap_err();
+    /// assert_eq!((e.position(), e.symbol()), (2, '2'));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let t = DecodeTable::new::<T>();
+        if !t.ascii {
+            return Self::from_str_with(s, |c| t.decode(c));
+        }
+
+        // Every symbol is one byte, and any other byte is an error
+        let mut v = Self::with_capacity(s.len());
+        for (i, &b) in s.as_bytes().iter().enumerate() {
+            let d = t.table[b as usize];
+            if d == INVALID {
+                // All the previous chars are ASCII, so i is a char boundary
+                return Err(ParseEnumVecError {
+                    position: i,
+                    symbol: s[i..].chars().next().unwrap(),
+                });
+            }
+            v.push(T::from_discr(d as usize));
+        }
+
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0: A, 1: B, ..., 999: the char 1000 code points after 'A'
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Letter(u32);
+
+    unsafe impl EnumLike for Letter {
+        const NUM_VARIANTS: usize = 1000;
+        fn to_discr(self) -> usize {
+            self.0 as usize
+        }
+        fn from_discr(x: usize) -> Self {
+            Letter(x as u32)
+        }
+    }
+
+    impl EnumSymbol for Letter {
+        fn symbol(self) -> char {
+            ::std::char::from_u32('A' as u32 + self.0).unwrap()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let ev: EnumVec<_> = (0..100u32).map(|i| i % 3 == 0).collect();
+        let s = ev.to_string();
+        assert_eq!(&s[..6], "100100");
+        assert_eq!(s.parse::<EnumVec<bool>>().unwrap(), ev);
+        assert_eq!("".parse::<EnumVec<bool>>().unwrap().len(), 0);
+
+        // Symbols outside the tables
+        let ev: EnumVec<_> = (0..1000).map(Letter).collect();
+        let s = ev.to_string();
+        assert_eq!(s.chars().count(), 1000);
+        assert!(s.starts_with("ABC"));
+        assert_eq!(s.parse::<EnumVec<Letter>>().unwrap(), ev);
+    }
+
+    #[test]
+    fn error_position() {
+        let e = "0110é01".parse::<EnumVec<bool>>().unwrap_err();
+        assert_eq!((e.position(), e.symbol()), (4, 'é'));
+        let e = "10 1".parse::<EnumVec<bool>>().unwrap_err();
+        assert_eq!((e.position(), e.symbol()), (2, ' '));
+        assert_eq!(e.to_string(), "invalid symbol ' ' at position 2");
+
+        let e = "ABé@".parse::<EnumVec<Letter>>().unwrap_err();
+        assert_eq!((e.position(), e.symbol()), (3, '@'));
+    }
+}
@@ -0,0 +1,310 @@
+//! An `EnumVec` which tracks its modified storage blocks.
+//!
+//! Every modification marks the storage blocks which contain the changed
+//! elements as dirty, using one bit per block. The dirty blocks, together
+//! with the length, are what needs to be written to update a saved copy of
+//! the vector, see `TrackedEnumVec::write_dirty`.
+
+use enum_like::EnumLike;
+use packed::{Header, BLOCK_BYTES, HEADER_LEN};
+use std::cmp::min;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::ops::{Deref, Range};
+use vec_u32::{EnumVec, Runs};
+
+/// An `EnumVec` which remembers which storage blocks were modified, so that
+/// only those blocks need to be saved or sent.
+///
+/// The wrapper dereferences to the `EnumVec`, but only the methods of
+/// `TrackedEnumVec` can modify it.
+///
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::TrackedEnumVec;
+///
+/// let mut tv = TrackedEnumVec::from(EnumVec::from_elem(false, 1000));
+/// assert!(!tv.is_dirty());
+/// tv.set(40, true);
+/// tv.set(900, true);
+/// assert_eq!(tv.count_ones(), 2);
+/// assert_eq!(tv.take_dirty(), vec![32..64, 896..928]);
+/// assert!(!tv.is_dirty());
+/// ```
+#[derive(Clone, Debug)]
+pub struct TrackedEnumVec<T: EnumLike> {
+    v: EnumVec<T>,
+    // One flag per storage block, the blocks after the end of dirty are
+    // clean. May be longer than the number of blocks after truncating.
+    dirty: EnumVec<bool>,
+    // Length when the blocks were last marked as clean. Removing elements
+    // at the end of a block does not leave any dirty block before the end.
+    clean_len: usize,
+}
+
+impl<T: EnumLike> TrackedEnumVec<T> {
+    /// Create an empty vector.
+    pub fn new() -> Self {
+        Self::from(EnumVec::new())
+    }
+
+    /// Returns the inner `EnumVec`, discarding the dirty blocks.
+    pub fn into_inner(self) -> EnumVec<T> {
+        self.v
+    }
+
+    // Mark the blocks which contain the elements start..end
+    fn mark(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let epb = EnumVec::<T>::ELEMS_PER_BLOCK;
+        let (first, last) = (start / epb, (end - 1) / epb);
+        if self.dirty.len() <= last {
+            self.dirty.resize(last + 1, false);
+        }
+        for b in first..=last {
+            self.dirty.set(b, true);
+        }
+    }
+
+    /// Set the element at position `i` to `x`.
+    pub fn set(&mut self, i: usize, x: T) {
+        self.v.set(i, x);
+        self.mark(i, i + 1);
+    }
+
+    /// Append an element at the end.
+    pub fn push(&mut self, x: T) {
+        self.v.push(x);
+        let len = self.v.len();
+        self.mark(len - 1, len);
+    }
+
+    /// Remove the last element and return it.
+    pub fn pop(&mut self) -> Option<T> {
+        let x = self.v.pop()?;
+        let len = self.v.len();
+        self.mark(len, len + 1);
+
+        Some(x)
+    }
+
+    /// Insert an element at position `i`, shifting the following elements.
+    pub fn insert(&mut self, i: usize, x: T) {
+        self.v.insert(i, x);
+        let len = self.v.len();
+        self.mark(i, len);
+    }
+
+    /// Remove the element at position `i`, shifting the following elements.
+    pub fn remove(&mut self, i: usize) -> T {
+        let len = self.v.len();
+        let x = self.v.remove(i);
+        self.mark(i, len);
+
+        x
+    }
+
+    /// Shorten the vector to `len` elements.
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = self.v.len();
+        self.v.truncate(len);
+        self.mark(len, old_len);
+    }
+
+    /// Returns `true` if any block or the length was modified since the
+    /// last call to `take_dirty` or `write_dirty`.
+    pub fn is_dirty(&self) -> bool {
+        self.v.len() != self.clean_len || self.dirty_ranges().next().is_some()
+    }
+
+    /// Iterator over the ranges of elements in the modified blocks. The
+    /// ranges are block-aligned, except at the end of the vector. A vector
+    /// which was shortened to a block boundary has no dirty ranges, but it
+    /// is still dirty, see `is_dirty`.
+    pub fn dirty_ranges(&self) -> DirtyRanges<'_> {
+        DirtyRanges {
+            runs: self.dirty.runs(),
+            block: 0,
+            elems_per_block: EnumVec::<T>::ELEMS_PER_BLOCK,
+            len: self.v.len(),
+        }
+    }
+
+    /// Returns the dirty ranges and marks all the blocks and the length as
+    /// clean.
+    pub fn take_dirty(&mut self) -> Vec<Range<usize>> {
+        let ranges = self.dirty_ranges().collect();
+        self.dirty.clear();
+        self.clean_len = self.v.len();
+
+        ranges
+    }
+
+    /// Update a copy of the vector in the packed format, by writing the
+    /// header and the modified blocks, and mark all the blocks as clean.
+    ///
+    /// `w` must contain the vector as it was when the blocks were last
+    /// marked as clean, written by `EnumVec::write_packed` at offset 0.
+    /// After truncating, the blocks after the end are left in `w`, which
+    /// is allowed by the format.
+    /// ```
+    /// use enum_vec::EnumVec;
+    /// use enum_vec::TrackedEnumVec;
+    /// use std::io::Cursor;
+    ///
+    /// let ev = EnumVec::from_elem(Some(false), 1000);
+    /// let mut file = Cursor::new(vec![]);
+    /// ev.write_packed(&mut file).unwrap();
+    ///
+    /// let mut tv = TrackedEnumVec::from(ev);
+    /// tv.set(500, None);
+    /// tv.push(Some(true));
+    /// tv.write_dirty(&mut file).unwrap();
+    ///
+    /// file.set_position(0);
+    /// assert_eq!(EnumVec::read_packed(&mut file).unwrap(), *tv);
+    /// ```
+    pub fn write_dirty<W: Write + Seek>(&mut self, w: &mut W) -> io::Result<()> {
+        let epb = EnumVec::<T>::ELEMS_PER_BLOCK;
+        for r in self.dirty_ranges() {
+            let first = r.start / epb;
+            let mut bytes = vec![];
+            for b in first..(r.end + epb - 1) / epb {
+                bytes.extend_from_slice(&self.v.masked_block(b).to_le_bytes());
+            }
+            w.seek(SeekFrom::Start((HEADER_LEN + first * BLOCK_BYTES) as u64))?;
+            w.write_all(&bytes)?;
+        }
+        w.seek(SeekFrom::Start(0))?;
+        w.write_all(&Header::new::<T>(self.v.len()).to_bytes())?;
+        self.dirty.clear();
+        self.clean_len = self.v.len();
+
+        Ok(())
+    }
+}
+
+impl<T: EnumLike> Default for TrackedEnumVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: EnumLike> From<EnumVec<T>> for TrackedEnumVec<T> {
+    /// Track the changes to `v`. All the blocks start clean.
+    fn from(v: EnumVec<T>) -> Self {
+        Self {
+            clean_len: v.len(),
+            v,
+            dirty: EnumVec::new(),
+        }
+    }
+}
+
+impl<T: EnumLike> Deref for TrackedEnumVec<T> {
+    type Target = EnumVec<T>;
+
+    fn deref(&self) -> &EnumVec<T> {
+        &self.v
+    }
+}
+
+/// Iterator returned by `TrackedEnumVec::dirty_ranges`
+pub struct DirtyRanges<'a> {
+    runs: Runs<'a, bool>,
+    // Index of the first block of the next run
+    block: usize,
+    elems_per_block: usize,
+    len: usize,
+}
+
+impl<'a> Iterator for DirtyRanges<'a> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (dirty, n) in &mut self.runs {
+            let start = self.block * self.elems_per_block;
+            self.block += n;
+            let end = min(self.block * self.elems_per_block, self.len);
+            if dirty && start < end {
+                return Some(start..end);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn tracks_blocks() {
+        let mut tv = TrackedEnumVec::from(EnumVec::from_elem(None, 100));
+        // 10 elements per block
+        tv.set(5, Some((true, false)));
+        tv.set(7, Some((false, true)));
+        assert_eq!(tv.dirty_ranges().collect::<Vec<_>>(), vec![0..10]);
+        tv.insert(95, Some((true, true)));
+        tv.push(None);
+        assert_eq!(tv.take_dirty(), vec![0..10, 90..102]);
+
+        tv.remove(50);
+        assert_eq!(tv.take_dirty(), vec![50..101]);
+        // The last block is dirty because it lost elements
+        tv.truncate(35);
+        assert_eq!(tv.dirty_ranges().collect::<Vec<_>>(), vec![30..35]);
+        tv.pop();
+        assert_eq!(tv.take_dirty(), vec![30..34]);
+        assert_eq!(tv.len(), 34);
+    }
+
+    #[test]
+    fn length_change_at_block_boundary() {
+        let mut tv = TrackedEnumVec::from(EnumVec::from_elem(true, 33));
+        tv.pop();
+        assert!(tv.is_dirty());
+        assert_eq!(tv.take_dirty(), vec![]);
+        assert!(!tv.is_dirty());
+
+        let ev = EnumVec::from_elem(Some(true), 100);
+        let mut file = Cursor::new(vec![]);
+        ev.write_packed(&mut file).unwrap();
+        let mut tv = TrackedEnumVec::from(ev);
+        // 16 elements per block
+        tv.truncate(64);
+        assert!(tv.is_dirty());
+        assert_eq!(tv.dirty_ranges().count(), 0);
+        tv.write_dirty(&mut file).unwrap();
+        assert!(!tv.is_dirty());
+        file.set_position(0);
+        assert_eq!(EnumVec::read_packed(&mut file).unwrap(), *tv);
+    }
+
+    #[test]
+    fn incremental_save() {
+        let v: Vec<_> = (0..1000usize).map(|i| i % 7 < 3).collect();
+        let mut file = Cursor::new(vec![]);
+        EnumVec::from(v.clone()).write_packed(&mut file).unwrap();
+
+        let mut tv = TrackedEnumVec::from(EnumVec::from(v.clone()));
+        let mut expected = v;
+        for (k, &i) in [3, 400, 401, 999].iter().enumerate() {
+            tv.set(i, k % 2 == 0);
+            expected[i] = k % 2 == 0;
+        }
+        tv.remove(10);
+        expected.remove(10);
+        tv.truncate(600);
+        expected.truncate(600);
+        tv.write_dirty(&mut file).unwrap();
+        assert!(!tv.is_dirty());
+
+        file.set_position(0);
+        let ev = EnumVec::<bool>::read_packed(&mut file).unwrap();
+        assert_eq!(ev.to_vec(), expected);
+    }
+}
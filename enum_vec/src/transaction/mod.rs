@@ -0,0 +1,232 @@
+//! Transactions over an `EnumVec`.
+//!
+//! A transaction borrows the vector mutably and logs the old value of every
+//! change, so that all the changes, or the ones after a savepoint, can be
+//! undone.
+
+use enum_like::EnumLike;
+use std::ops::Deref;
+use vec_u32::EnumVec;
+
+// How to undo one change, using raw discriminants
+#[derive(Copy, Clone, Debug)]
+enum Undo {
+    // Element at this index had this discriminant
+    Set(usize, usize),
+    // An element was pushed
+    Push,
+    // An element with this discriminant was popped
+    Pop(usize),
+}
+
+/// A position in the log of a `Transaction`, returned by
+/// `Transaction::savepoint`.
+///
+/// A savepoint is only the length of the log, so it must be used with the
+/// transaction which created it. Passing it to another transaction is not
+/// detected, and undoes the changes of that transaction after the same
+/// position.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Savepoint(usize);
+
+/// A set of changes to an `EnumVec` which can be undone, returned by
+/// `EnumVec::begin`.
+///
+/// Every change is logged with the old discriminant, so the log grows with
+/// the number of changes and not with the length of the vector. The changes
+/// are rolled back when the transaction is dropped without calling
+/// `commit`.
+///
+/// ```
+/// use enum_vec::EnumVec;
+///
+/// let mut ev: EnumVec<_> = vec![false; 100].into();
+/// {
+///     let mut t = ev.begin();
+///     t.set(5, true);
+///     t.push(true);
+///     assert_eq!(t.count_ones(), 2);
+///     t.rollback();
+/// }
+/// assert_eq!(ev.count_ones(), 0);
+///
+/// let mut t = ev.begin();
+/// t.set(5, true);
+/// t.commit();
+/// assert_eq!(ev.get(5), Some(true));
+/// ```
+pub struct Transaction<'a, T: 'a + EnumLike> {
+    v: &'a mut EnumVec<T>,
+    log: Vec<Undo>,
+}
+
+impl<T: EnumLike> EnumVec<T> {
+    /// Start a transaction. The vector can only be modified through the
+    /// transaction until it is committed or rolled back.
+    pub fn begin(&mut self) -> Transaction<'_, T> {
+        Transaction {
+            v: self,
+            log: vec![],
+        }
+    }
+}
+
+impl<'a, T: EnumLike> Transaction<'a, T> {
+    /// Set the element at position `i` to `x`.
+    ///
+    /// # Panics
+    ///
+    /// If `i` is out of bounds.
+    pub fn set(&mut self, i: usize, x: T) {
+        let old = self.v.get(i).map(T::to_discr);
+        self.v.set(i, x);
+        // set did not panic, so old is Some
+        self.log.push(Undo::Set(i, old.unwrap()));
+    }
+
+    /// Append an element at the end.
+    pub fn push(&mut self, x: T) {
+        self.v.push(x);
+        self.log.push(Undo::Push);
+    }
+
+    /// Remove the last element and return it.
+    pub fn pop(&mut self) -> Option<T> {
+        let x = self.v.pop()?;
+        self.log.push(Undo::Pop(x.to_discr()));
+
+        Some(x)
+    }
+
+    /// Returns the current position in the log. The changes made after
+    /// this call can be undone with `rollback_to`.
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint(self.log.len())
+    }
+
+    /// Undo the changes made after `savepoint` was created, keeping the
+    /// earlier ones. The savepoints created after `savepoint` become
+    /// invalid, but `savepoint` itself can be used again.
+    ///
+    /// # Panics
+    ///
+    /// If the savepoint is invalid because the transaction was already
+    /// rolled back to an earlier savepoint. A savepoint created by another
+    /// transaction is not detected, see `Savepoint`.
+    /// ```
+    /// use enum_vec::EnumVec;
+    ///
+    /// let mut ev: EnumVec<_> = vec![None; 10].into();
+    /// let mut t = ev.begin();
+    /// t.set(0, Some(true));
+    /// let sp = t.savepoint();
+    /// t.set(1, Some(true));
+    /// t.pop();
+    /// t.rollback_to(sp);
+    /// assert_eq!((t.get(1), t.len()), (Some(None), 10));
+    /// t.commit();
+    /// assert_eq!(ev.get(0), Some(Some(true)));
+    /// ```
+    pub fn rollback_to(&mut self, savepoint: Savepoint) {
+        assert!(
+            savepoint.0 <= self.log.len(),
+            "invalid savepoint: {} > {}",
+            savepoint.0,
+            self.log.len()
+        );
+
+        while self.log.len() > savepoint.0 {
+            match self.log.pop().unwrap() {
+                Undo::Set(i, discr) => unsafe {
+                    // The index was valid when the change was made, and
+                    // the following changes were already undone
+                    self.v.set_raw_unchecked(i, discr);
+                },
+                Undo::Push => {
+                    self.v.pop();
+                }
+                Undo::Pop(discr) => self.v.push(T::from_discr(discr)),
+            }
+        }
+    }
+
+    /// Keep all the changes.
+    pub fn commit(mut self) {
+        self.log.clear();
+    }
+
+    /// Undo all the changes. This is the same as dropping the transaction.
+    pub fn rollback(self) {}
+}
+
+impl<'a, T: EnumLike> Deref for Transaction<'a, T> {
+    type Target = EnumVec<T>;
+
+    fn deref(&self) -> &EnumVec<T> {
+        self.v
+    }
+}
+
+impl<'a, T: EnumLike> Drop for Transaction<'a, T> {
+    fn drop(&mut self) {
+        self.rollback_to(Savepoint(0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_savepoints() {
+        let v: Vec<_> = (0..50usize)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i % 5))
+            .collect();
+        let mut ev = EnumVec::from(v.clone());
+        let mut t = ev.begin();
+        t.set(3, None);
+        let a = t.savepoint();
+        t.pop();
+        t.push(Some((true, true)));
+        t.set(49, None);
+        let b = t.savepoint();
+        for _ in 0..10 {
+            t.pop();
+        }
+        t.set(0, None);
+        t.rollback_to(b);
+        assert_eq!(t.len(), 50);
+        assert_eq!(t.get(49), Some(None));
+        t.rollback_to(a);
+        assert_eq!(t.get(49), Some(v[49]));
+        t.set(1, None);
+        t.rollback_to(a);
+        assert_eq!(t.get(3), Some(None));
+        t.rollback();
+        assert_eq!(ev.to_vec(), v);
+    }
+
+    #[test]
+    fn rollback_on_drop() {
+        let mut ev: EnumVec<_> = vec![true; 40].into();
+        {
+            let mut t = ev.begin();
+            while t.pop().is_some() {}
+            t.push(false);
+            assert_eq!(t.len(), 1);
+        }
+        assert_eq!(ev, vec![true; 40].into());
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_savepoint() {
+        let mut ev: EnumVec<_> = vec![true; 10].into();
+        let mut t = ev.begin();
+        let a = t.savepoint();
+        t.set(0, false);
+        let b = t.savepoint();
+        t.rollback_to(a);
+        t.rollback_to(b);
+    }
+}
@@ -0,0 +1,264 @@
+use enum_like::EnumLike;
+use packed::{first_invalid, FormatError, Header, BLOCK_BYTES, HEADER_LEN};
+use std::fmt;
+use std::marker::PhantomData;
+use vec_u32::packing;
+use vec_u32::EnumVec;
+
+/// A read-only view of a vector stored in the packed format, borrowing the
+/// bytes instead of copying them.
+///
+/// The header and all the discriminants are validated once, when the view
+/// is created. The bytes do not need to be aligned. None of the methods
+/// allocate.
+///
+/// ```
+/// use enum_vec::{EnumVec, EnumVecRef};
+///
+/// let ev: EnumVec<_> = vec![Some(true), None, Some(true)].into();
+/// let mut buf = vec![];
+/// ev.write_packed(&mut buf).unwrap();
+///
+/// let v = EnumVecRef::<Option<bool>>::new(&buf).unwrap();
+/// assert_eq!(v.len(), 3);
+/// assert_eq!(v.get(1), Some(None));
+/// assert_eq!(v.count(Some(true)), 2);
+/// assert!(v.iter().eq(ev.iter()));
+/// ```
+#[derive(Copy, Clone)]
+pub struct EnumVecRef<'a, T: EnumLike> {
+    // The blocks, without the header
+    blocks: &'a [u8],
+    num_elements: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T: EnumLike> EnumVecRef<'a, T> {
+    const BITS: usize = EnumVec::<T>::BITS_PER_ELEM;
+    const ELEMS_PER_BLOCK: usize = EnumVec::<T>::ELEMS_PER_BLOCK;
+
+    /// Creates a view of the packed vector at the start of `bytes`, checking
+    /// that every element is a valid discriminant of `T`. Bytes after the
+    /// last block are ignored.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, FormatError> {
+        let header = Header::from_bytes(bytes)?;
+        if header.is_stream() {
+            return Err(FormatError::UnknownLength);
+        }
+        header.check::<T>()?;
+        let len = header.len as usize;
+        let num_blocks = header.num_blocks();
+        let blocks = &bytes[HEADER_LEN..];
+        if blocks.len() / BLOCK_BYTES < num_blocks {
+            return Err(FormatError::Truncated);
+        }
+        let v = Self {
+            blocks: &blocks[..num_blocks * BLOCK_BYTES],
+            num_elements: len,
+            phantom: PhantomData,
+        };
+        for b in 0..num_blocks {
+            if let Some(k) = first_invalid::<T>(v.block(b), v.elems_in_block(b)) {
+                let i = b * Self::ELEMS_PER_BLOCK + k;
+                return Err(FormatError::InvalidDiscriminant(i));
+            }
+        }
+
+        Ok(v)
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.num_elements
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.blocks.len() / BLOCK_BYTES
+    }
+
+    fn block(&self, b: usize) -> u32 {
+        let x = &self.blocks[b * BLOCK_BYTES..(b + 1) * BLOCK_BYTES];
+        u32::from_le_bytes([x[0], x[1], x[2], x[3]])
+    }
+
+    // Number of elements stored in block b
+    fn elems_in_block(&self, b: usize) -> usize {
+        ::std::cmp::min(
+            Self::ELEMS_PER_BLOCK,
+            self.num_elements - b * Self::ELEMS_PER_BLOCK,
+        )
+    }
+
+    /// Returns the element at position `i`.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len() {
+            return None;
+        }
+        let (b, shift) = packing::block_index(Self::BITS, i);
+        let x = (self.block(b) >> shift) & packing::element_mask(Self::BITS);
+
+        Some(T::from_discr(x as usize))
+    }
+
+    /// Iterator over the elements.
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter { v: *self, idx: 0 }
+    }
+
+    /// Check whether any of the elements is equal to `x`, using the same
+    /// bithacks as `EnumVec::any`.
+    pub fn any(&self, x: T) -> bool {
+        let x = x.to_discr();
+        (0..self.num_blocks()).any(|b| {
+            packing::any(&[self.block(b)], Self::BITS, self.elems_in_block(b), x)
+        })
+    }
+
+    /// Check whether all of the elements are equal to `x`, using the same
+    /// bithacks as `EnumVec::all`.
+    pub fn all(&self, x: T) -> bool {
+        let x = x.to_discr();
+        (0..self.num_blocks()).all(|b| {
+            packing::all(&[self.block(b)], Self::BITS, self.elems_in_block(b), x)
+        })
+    }
+
+    /// Count the elements equal to `x`, using the same bithacks as
+    /// `EnumVec::count`.
+    pub fn count(&self, x: T) -> usize {
+        let x = x.to_discr();
+        (0..self.num_blocks())
+            .map(|b| {
+                let n = self.elems_in_block(b);
+                packing::count_between(&[self.block(b)], Self::BITS, x, 0, n)
+            })
+            .sum()
+    }
+
+    /// Copies the elements into an `EnumVec`.
+    pub fn to_enum_vec(&self) -> EnumVec<T> {
+        let storage = (0..self.num_blocks()).map(|b| self.block(b)).collect();
+
+        // The discriminants were checked in new
+        unsafe { EnumVec::from_storage(storage, self.num_elements) }
+    }
+}
+
+impl<'a, T: EnumLike + fmt::Debug> fmt::Debug for EnumVecRef<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, 'b, T: EnumLike> PartialEq<EnumVecRef<'b, T>> for EnumVecRef<'a, T> {
+    fn eq(&self, other: &EnumVecRef<'b, T>) -> bool {
+        self.len() == other.len()
+            && self.iter().map(T::to_discr).eq(other.iter().map(T::to_discr))
+    }
+}
+
+impl<'a, T: EnumLike> Eq for EnumVecRef<'a, T> {}
+
+impl<'a, T: EnumLike> From<EnumVecRef<'a, T>> for EnumVec<T> {
+    fn from(v: EnumVecRef<'a, T>) -> Self {
+        v.to_enum_vec()
+    }
+}
+
+impl<'a, T: EnumLike> IntoIterator for EnumVecRef<'a, T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over EnumVecRef
+pub struct Iter<'a, T: EnumLike> {
+    v: EnumVecRef<'a, T>,
+    idx: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let x = self.v.get(self.idx)?;
+        self.idx += 1;
+
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.v.len() - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: EnumLike> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packed<T: EnumLike>(ev: &EnumVec<T>) -> Vec<u8> {
+        let mut buf = vec![];
+        ev.write_packed(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn matches_enum_vec() {
+        // 3 bits per element, with 2 wasted bits in each block
+        let v: Vec<_> = (0..1000usize)
+            .map(|i| <Option<(bool, bool)>>::from_discr(i * 7 % 5))
+            .collect();
+        let ev = EnumVec::from(v);
+        // Unaligned, with trailing bytes
+        let mut buf = vec![0xAA];
+        buf.extend(packed(&ev));
+        buf.push(0xFF);
+        let r = EnumVecRef::<Option<(bool, bool)>>::new(&buf[1..]).unwrap();
+        assert_eq!(r.to_enum_vec(), ev);
+        assert!(r.iter().eq(ev.iter()));
+        for i in 0..5 {
+            let x = <Option<(bool, bool)>>::from_discr(i);
+            assert_eq!(r.count(x), ev.count(x));
+            assert!(r.any(x));
+            assert!(!r.all(x));
+        }
+        assert_eq!(r.get(1000), None);
+
+        let ev: EnumVec<_> = vec![true; 33].into();
+        let buf = packed(&ev);
+        let r = EnumVecRef::<bool>::new(&buf).unwrap();
+        assert!(r.all(true));
+        assert!(!r.any(false));
+        assert_eq!(r.count(true), 33);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let ev: EnumVec<_> = vec![None, Some(true), Some(false)].into();
+        let buf = packed(&ev);
+        assert!(EnumVecRef::<Option<bool>>::new(&buf).is_ok());
+        assert_eq!(
+            EnumVecRef::<Option<bool>>::new(&buf[..buf.len() - 1]),
+            Err(FormatError::Truncated)
+        );
+        assert!(EnumVecRef::<bool>::new(&buf).is_err());
+        // Set element 2 to the invalid discriminant 3
+        let mut bad = buf.clone();
+        bad[HEADER_LEN] |= 0b11 << 4;
+        assert_eq!(
+            EnumVecRef::<Option<bool>>::new(&bad),
+            Err(FormatError::InvalidDiscriminant(2))
+        );
+    }
+}
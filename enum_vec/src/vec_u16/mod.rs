@@ -1,8 +1,10 @@
-use enum_like::EnumLike;
+use enum_like::{EnumLike, EnumTable};
 use std::fmt;
 use std::iter::{FromIterator, repeat};
 use std::marker::PhantomData;
-use std::ops::Range;
+use std::ops::{Index, Range};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
+use std::ops::{Not, Shl, ShlAssign, Shr, ShrAssign};
 use std::hash::{Hash, Hasher};
 use std::cmp;
 
@@ -10,6 +12,227 @@ type StorageBlock = u16;
 type Storage = Vec<StorageBlock>;
 const STORAGE_BLOCK_SIZE: usize = 16;
 
+/// Functions to pack elements of a fixed number of bits into storage
+/// blocks. `EnumVec` uses them with `BITS_PER_ELEM`, which is known at
+/// compile time, and `DynEnumVec` with a number of bits chosen at runtime.
+pub(crate) mod packing {
+    use super::{StorageBlock, STORAGE_BLOCK_SIZE};
+
+    pub(crate) const fn elems_per_block(bits: usize) -> usize {
+        STORAGE_BLOCK_SIZE / bits
+    }
+
+    // Mask with the lowest bits set, built in StorageBlock so that an
+    // element can use the whole block
+    pub(crate) const fn element_mask(bits: usize) -> StorageBlock {
+        !0 >> (STORAGE_BLOCK_SIZE - bits)
+    }
+
+    // Magic constants used to process many elements at once, see any().
+    // Mask the unused bits to 0. Eg. if bits = 15 and
+    // STORAGE_BLOCK_SIZE = 32, the 2 most significant bits are unused.
+    pub(crate) const fn valid_mask(bits: usize) -> StorageBlock {
+        !0 >> (STORAGE_BLOCK_SIZE % bits)
+    }
+
+    // A mask where all the elements have discriminant value of 1
+    pub(crate) const fn one_mask(bits: usize) -> StorageBlock {
+        (!0 / element_mask(bits)) >> (STORAGE_BLOCK_SIZE % bits)
+    }
+
+    // A mask where the highest bit of each element is set to 1
+    pub(crate) const fn high_mask(bits: usize) -> StorageBlock {
+        one_mask(bits) << (bits - 1)
+    }
+
+    // returns pair: (block, bit offset inside block)
+    // bit offset means bit shift left
+    // use ((storage[block] >> bit_offset) & element_mask(bits)) to get the value
+    #[inline]
+    pub(crate) fn block_index(bits: usize, i: usize) -> (usize, usize) {
+        (
+            i / elems_per_block(bits),
+            (i % elems_per_block(bits)) * bits,
+        )
+    }
+
+    #[inline]
+    pub(crate) fn blocks_for_elements(bits: usize, n: usize) -> usize {
+        n.saturating_add(elems_per_block(bits) - 1) / elems_per_block(bits)
+    }
+
+    /// Get element i without bounds checking
+    #[inline]
+    pub(crate) unsafe fn get_unchecked(
+        storage: &[StorageBlock],
+        bits: usize,
+        i: usize,
+    ) -> usize {
+        let (idx_w, idx_b) = block_index(bits, i);
+        let block = storage.get_unchecked(idx_w);
+        let x = (block >> idx_b) & element_mask(bits);
+
+        x as usize
+    }
+
+    /// Set element i without bounds checking. x must fit in bits.
+    #[inline]
+    pub(crate) unsafe fn set_unchecked(
+        storage: &mut [StorageBlock],
+        bits: usize,
+        i: usize,
+        x: usize,
+    ) {
+        let (idx_w, idx_b) = block_index(bits, i);
+        let block = storage.get_unchecked_mut(idx_w);
+        *block &= !(element_mask(bits) << idx_b);
+        *block |= (x as StorageBlock) << idx_b;
+
+        // Alternative implementation, TODO: benchmark
+        /*
+        let x_old = (*block >> idx_b) & element_mask(bits);
+        *block ^= (x_old ^ x as StorageBlock) << idx_b; 
+        */
+    }
+
+    // Check whether any of the first len elements is equal to x
+    #[inline]
+    pub(crate) fn any(
+        storage: &[StorageBlock],
+        bits: usize,
+        len: usize,
+        x: usize,
+    ) -> bool {
+        // This could be magically inserted by LLVM, but I have not seen the
+        // assembly yet, so just to be sure:
+        if len == 0 {
+            return false;
+        }
+        let (last_block, last_elem_shift) = block_index(bits, len);
+        // Process all the other blocks, which are complete
+
+        let one_mask = one_mask(bits);
+        let high_mask = high_mask(bits);
+        // A mask where all the elements have discriminant value of x
+        let x_mask = x as StorageBlock * one_mask;
+        // For example, if we set bits_per_elem to 4:
+        // one_mask  = 0x11111111;
+        // high_mask = 0x88888888;
+        // x_mask    = 0x33333333; (when x = 3)
+
+        // https://graphics.stanford.edu/~seander/bithacks.html
+        // #define haszero(v) (((v) - 0x01010101UL) & ~(v) & 0x80808080UL)
+        // #define hasvalue(x,n) (haszero((x) ^ (~0UL/255 * (n))))
+        let haszero = |v: StorageBlock| -> bool {
+            ((v.wrapping_sub(one_mask as StorageBlock)) & !v & high_mask) != 0
+        };
+
+        for block in &storage[..last_block] {
+            if haszero(block ^ x_mask) {
+                return true;
+            }
+        }
+
+        // Check last block
+        // This branch can be safely removed, as haszero(0xFFFFFFFF) will return false
+        // So it could be replaced by return haszero(... | last_block_mask)
+        if last_elem_shift != 0 {
+            // Mask which sets the remaining elements to all ones, so they
+            // do not affect the result of haszero
+            let last_block_mask = !0 << (last_elem_shift);
+            if haszero((storage[last_block] ^ x_mask) | last_block_mask) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // Check whether all of the first len elements are equal to x
+    #[inline]
+    pub(crate) fn all(
+        storage: &[StorageBlock],
+        bits: usize,
+        len: usize,
+        x: usize,
+    ) -> bool {
+        if len == 0 {
+            return true;
+        }
+        let (last_block, last_elem_shift) = block_index(bits, len);
+
+        let valid_mask = valid_mask(bits);
+        // A mask where all the elements have discriminant value of x
+        let x_mask = x as StorageBlock * one_mask(bits);
+        // For example, if we set bits_per_elem to 4:
+        // one_mask  = 0x11111111;
+        // x_mask    = 0x33333333; (when x = 3)
+        // Then checking for all(x == 3) is as easy as storage[i] == x_mask
+
+        for block in &storage[..last_block] {
+            if block & valid_mask != x_mask {
+                return false;
+            }
+        }
+
+        // Check last block
+        // This branch can be safely removed, but I am not sure about the performance
+        // differences.
+        if last_elem_shift != 0 {
+            let last_block_mask = !(!0 << (last_elem_shift));
+            if storage[last_block] & last_block_mask != x_mask & last_block_mask {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Count the elements equal to x, starting from the first element of
+    // block first_block, up to element end (exclusive).
+    #[inline]
+    pub(crate) fn count_between(
+        storage: &[StorageBlock],
+        bits: usize,
+        x: usize,
+        first_block: usize,
+        end: usize,
+    ) -> usize {
+        let (last_block, last_elem_shift) = block_index(bits, end);
+        let x_mask = x as StorageBlock * one_mask(bits);
+        // The elements equal to x will be zero after xoring with x_mask, so
+        // we count the non-zero elements instead
+        let mut nonzero = 0;
+        for block in &storage[first_block..last_block] {
+            let flags = nonzero_elements(bits, block ^ x_mask);
+            nonzero += flags.count_ones() as usize;
+        }
+        let mut total = (last_block - first_block) * elems_per_block(bits);
+        if last_elem_shift != 0 {
+            let last_block_mask = !(!0 << last_elem_shift);
+            let v = storage[last_block] ^ x_mask;
+            let flags = nonzero_elements(bits, v) & last_block_mask;
+            nonzero += flags.count_ones() as usize;
+            total += last_elem_shift / bits;
+        }
+
+        total - nonzero
+    }
+
+    // Returns a block where the highest bit of each element is set to 1 if
+    // that element is not zero, and all the other bits are 0.
+    // Unlike haszero, this has no false positives, so the result can be used
+    // to count the elements.
+    #[inline]
+    pub(crate) fn nonzero_elements(bits: usize, v: StorageBlock) -> StorageBlock {
+        // The low bits of each element are all ones, adding them to the
+        // element will carry into the high bit if any of them is set. There
+        // is no carry into the next element.
+        let low_mask = valid_mask(bits) & !high_mask(bits);
+        (((v & low_mask) + low_mask) | v) & high_mask(bits)
+    }
+}
+
 /// A vector which efficiently stores enum variants.
 #[derive(Clone)]
 pub struct EnumVec<T: EnumLike> {
@@ -33,7 +256,7 @@ impl<T: EnumLike> EnumVec<T> {
     // }
     // Also, this isn't always a power of two
     /// How many bits are needed to store a variant
-    const BITS_PER_ELEM: usize = (T::NUM_VARIANTS > (1 << 0)) as usize
+    pub(crate) const MIN_BITS_PER_ELEM: usize = (T::NUM_VARIANTS > (1 << 0)) as usize
         + (T::NUM_VARIANTS > (1 << 1)) as usize
         + (T::NUM_VARIANTS > (1 << 2)) as usize
         + (T::NUM_VARIANTS > (1 << 3)) as usize
@@ -64,9 +287,35 @@ impl<T: EnumLike> EnumVec<T> {
         + (T::NUM_VARIANTS > (1 << 28)) as usize
         + (T::NUM_VARIANTS > (1 << 29)) as usize
         + (T::NUM_VARIANTS > (1 << 30)) as usize
-        + (T::NUM_VARIANTS > (1 << 31)) as usize
+        + (T::NUM_VARIANTS > (1 << 31)) as usize;
+
+    /// How many bits are used to store a variant: the minimum, unless the
+    /// type reserves more bits using `EnumLike::RESERVED_BITS`.
+    /// Reserving less bits than the minimum is a compile error:
+    /// ```compile_fail
+    /// #[macro_use]
+    /// extern crate enum_like_derive;
+    /// extern crate enum_like;
+    /// extern crate enum_vec;
+    /// use enum_vec::vec_u16::EnumVec;
+    ///
+    /// #[derive(Copy, Clone, EnumLike)]
+    /// #[enum_like(reserve_bits = 1)]
+    /// enum Trit { A, B, C }
+    ///
+    /// fn main() {
+    ///     let mut v = EnumVec::new();
+    ///     v.push(Trit::C);
+    /// }
+    /// ```
+    pub(crate) const BITS_PER_ELEM: usize = if T::RESERVED_BITS > Self::MIN_BITS_PER_ELEM {
+            T::RESERVED_BITS
+        } else {
+            Self::MIN_BITS_PER_ELEM
+        }
         + Self::ERROR_TOO_MANY_VARIANTS
-        + Self::ERROR_ZERO_SIZED;
+        + Self::ERROR_ZERO_SIZED
+        + Self::ERROR_RESERVED_BITS;
 
     const ERROR_TOO_MANY_VARIANTS: usize = 0 /*Error: this type has too many variants for this storage, try using enum_vec::vec_u64::EnumVec*/ - ((T::NUM_VARIANTS as u64 > (1 << STORAGE_BLOCK_SIZE) ) as usize);
 
@@ -75,12 +324,22 @@ impl<T: EnumLike> EnumVec<T> {
         // Error: cannot use EnumVec for zero-sized types
         - ((T::NUM_VARIANTS <= 1) as usize);
 
+    const ERROR_RESERVED_BITS: usize = 0
+        // Error: RESERVED_BITS is too small to store all the variants
+        - ((T::RESERVED_BITS != 0 && T::RESERVED_BITS < Self::MIN_BITS_PER_ELEM) as usize)
+        // Error: RESERVED_BITS is too large for this storage
+        - ((T::RESERVED_BITS > STORAGE_BLOCK_SIZE) as usize);
+
     // How to handle zero sized types?
     //const ZERO_SIZED: bool = Self::BITS_PER_ELEM == 0;
 
-    const ELEMS_PER_BLOCK: usize = STORAGE_BLOCK_SIZE / Self::BITS_PER_ELEM;
-    // While wrapping_shl is not const fn, we support at most 64 bits per element
-    const ELEMENT_MASK: StorageBlock = ((1u64 << Self::BITS_PER_ELEM) - 1) as StorageBlock;
+    pub(crate) const ELEMS_PER_BLOCK: usize = packing::elems_per_block(Self::BITS_PER_ELEM);
+    const ELEMENT_MASK: StorageBlock = packing::element_mask(Self::BITS_PER_ELEM);
+    // Magic constants used to process many elements at once, see the
+    // packing module.
+    const VALID_MASK: StorageBlock = packing::valid_mask(Self::BITS_PER_ELEM);
+    const ONE_MASK: StorageBlock = packing::one_mask(Self::BITS_PER_ELEM);
+
     pub fn new() -> Self {
         Default::default()
     }
@@ -208,7 +467,8 @@ impl<T: EnumLike> EnumVec<T> {
         let shift_storage = |block: &mut StorageBlock, at_zero: StorageBlock| {
             let last_bit_offset = (Self::ELEMS_PER_BLOCK - 1) * Self::BITS_PER_ELEM;
             let last = *block >> last_bit_offset & Self::ELEMENT_MASK;
-            *block <<= Self::BITS_PER_ELEM;
+            // An element may use the whole block
+            *block = block.checked_shl(Self::BITS_PER_ELEM as u32).unwrap_or(0);
             *block |= at_zero;
 
             last
@@ -275,7 +535,7 @@ impl<T: EnumLike> EnumVec<T> {
         let shift_storage = |block: &mut StorageBlock, at_zero: StorageBlock| {
             let last = *block & Self::ELEMENT_MASK;
             let end_bit_offset = (Self::ELEMS_PER_BLOCK - 1) * Self::BITS_PER_ELEM;
-            *block >>= Self::BITS_PER_ELEM;
+            *block = block.checked_shr(Self::BITS_PER_ELEM as u32).unwrap_or(0);
             *block |= at_zero << end_bit_offset;
 
             last
@@ -536,11 +796,7 @@ impl<T: EnumLike> EnumVec<T> {
 
     /// Get the raw discriminant without bounds checking
     pub unsafe fn get_raw_unchecked(&self, i: usize) -> usize {
-        let (idx_w, idx_b) = Self::block_index(i);
-        let block = self.storage.get_unchecked(idx_w);
-        let discr = (block >> idx_b) & Self::ELEMENT_MASK;
-
-        discr as usize
+        packing::get_unchecked(&self.storage, Self::BITS_PER_ELEM, i)
     }
 
     fn set_raw(&mut self, i: usize, discr: usize) {
@@ -556,16 +812,7 @@ impl<T: EnumLike> EnumVec<T> {
     /// Set the raw discriminant without bounds checking. It is assumed that
     /// the discriminant is lower than `T::NUM_ELEMENTS`.
     pub unsafe fn set_raw_unchecked(&mut self, i: usize, discr: usize) {
-        let (idx_w, idx_b) = Self::block_index(i);
-        let block = self.storage.get_unchecked_mut(idx_w);
-        *block &= !(Self::ELEMENT_MASK << idx_b);
-        *block |= (discr as StorageBlock) << idx_b;
-
-        // Alternative implementation, TODO: benchmark
-        /*
-        let discr_old = (*block >> idx_b) & Self::ELEMENT_MASK;
-        *block ^= (discr_old ^ discr as StorageBlock) << idx_b; 
-        */
+        packing::set_unchecked(&mut self.storage, Self::BITS_PER_ELEM, i, discr);
     }
 
     /// Swap two elements.
@@ -595,20 +842,76 @@ impl<T: EnumLike> EnumVec<T> {
     // bit offset means bit shift left
     // use ((self.storage[block] >> bit_offset) & ELEMENT_MASK) to get the value
     fn block_index(i: usize) -> (usize, usize) {
-        (
-            i / Self::ELEMS_PER_BLOCK,
-            (i % Self::ELEMS_PER_BLOCK) * Self::BITS_PER_ELEM,
-        )
+        packing::block_index(Self::BITS_PER_ELEM, i)
     }
 
     fn blocks_for_elements(n: usize) -> usize {
-        n.saturating_add(Self::ELEMS_PER_BLOCK - 1) / Self::ELEMS_PER_BLOCK
+        packing::blocks_for_elements(Self::BITS_PER_ELEM, n)
+    }
+
+    // Returns storage block b with the unused bits set to 0: the bits after
+    // the last element, and the wasted bits when STORAGE_BLOCK_SIZE is not a
+    // multiple of BITS_PER_ELEM.
+    // b must be lower than Self::blocks_for_elements(self.len())
+    pub(crate) fn masked_block(&self, b: usize) -> StorageBlock {
+        let (last_block, last_elem_shift) = Self::block_index(self.len());
+        let block = self.storage[b] & Self::VALID_MASK;
+        if b == last_block {
+            block & !(!0 << last_elem_shift)
+        } else {
+            block
+        }
     }
 
     pub fn iter<'a>(&'a self) -> EnumVecIter<'a, T> {
         (&self).into_iter()
     }
 
+    /// Iterator over the runs of equal elements, as `(element, run length)`
+    /// pairs. Blocks where all the elements belong to the same run are
+    /// skipped at once.
+    /// ```
+    /// use enum_vec::vec_u16::EnumVec;
+    ///
+    /// let mut ev = EnumVec::from_elem(false, 100);
+    /// ev.push(true);
+    /// ev.push(false);
+    /// let runs: Vec<_> = ev.runs().collect();
+    /// assert_eq!(runs, vec![(false, 100), (true, 1), (false, 1)]);
+    /// ```
+    pub fn runs(&self) -> Runs<'_, T> {
+        Runs { v: self, idx: 0 }
+    }
+
+    // Returns the length of the run of elements equal to the element at
+    // index start
+    fn run_length(&self, start: usize) -> usize {
+        let len = self.len();
+        let x = unsafe { self.get_raw_unchecked(start) };
+        let x_mask = x as StorageBlock * Self::ONE_MASK;
+        let mut i = start + 1;
+        // Check one by one until the end of the block
+        while i < len && Self::block_index(i).1 != 0 {
+            if unsafe { self.get_raw_unchecked(i) } != x {
+                return i - start;
+            }
+            i += 1;
+        }
+        // Skip the complete blocks with all the elements equal to x
+        while i + Self::ELEMS_PER_BLOCK <= len
+            && self.storage[i / Self::ELEMS_PER_BLOCK] & Self::VALID_MASK
+                == x_mask
+        {
+            i += Self::ELEMS_PER_BLOCK;
+        }
+        // And check the remaining elements one by one
+        while i < len && unsafe { self.get_raw_unchecked(i) } == x {
+            i += 1;
+        }
+
+        i - start
+    }
+
     // iter_mut cannot be implemented because we cannot take a reference to
     // the values inside the EnumVec. Use for_each() instead.
     /*
@@ -731,6 +1034,35 @@ impl<T: EnumLike> EnumVec<T> {
         &mut self.storage
     }
 
+    /// Creates an `EnumVec` with `len` elements from its internal storage.
+    ///
+    /// # Safety
+    ///
+    /// Every element must be a valid discriminant, lower than
+    /// `T::NUM_VARIANTS`. The storage must have enough blocks for `len`
+    /// elements, otherwise it is padded with zeros.
+    /// ```
+    /// use enum_vec::vec_u16::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, true].into();
+    /// let storage = ev.storage().clone();
+    /// let ev2 = unsafe { EnumVec::<bool>::from_storage(storage, 3) };
+    /// assert_eq!(ev, ev2);
+    /// ```
+    pub unsafe fn from_storage(storage: Storage, len: usize) -> Self {
+        let mut v = Self {
+            storage,
+            num_elements: len,
+            phantom: PhantomData,
+        };
+        let num_blocks = Self::blocks_for_elements(len);
+        if v.storage.len() < num_blocks {
+            v.storage.resize(num_blocks, 0);
+        }
+
+        v
+    }
+
     /// Check whether any of the elements is equal to `x`.
     /// This method uses arcane bithack magic to test many elements at once.
     /// ```
@@ -762,57 +1094,7 @@ impl<T: EnumLike> EnumVec<T> {
     /// assert_eq!(ev.any(None), true);
     /// ```
     pub fn any(&self, x: T) -> bool {
-        // This could be magically inserted by LLVM, but I have not seen the
-        // assembly yet, so just to be sure:
-        if self.is_empty() {
-            return false;
-        }
-        let (last_block, last_elem_shift) = Self::block_index(self.len());
-        // Process all the other blocks, which are complete
-
-        // Mask the unused bits to 0. Eg. if BITS_PER_ELEM = 15 and
-        // STORAGE_BLOCK_SIZE = 30, the 2 most significant bits are unused.
-        //let valid_mask = !0 >> (STORAGE_BLOCK_SIZE % Self::BITS_PER_ELEM);
-        // 2^BITS_PER_ELEM - 1
-        let num_variants_p2 = Self::ELEMENT_MASK;
-        // A mask where all the elements have discriminant value of 1
-        let one_mask = (!0 / num_variants_p2) >> (STORAGE_BLOCK_SIZE % Self::BITS_PER_ELEM);
-        // A mask where the highest bit of each element is set to 1
-        let high_mask = one_mask << (Self::BITS_PER_ELEM - 1);
-        // A mask where all the elements have discriminant value of x
-        let x = x.to_discr();
-        let x_mask = x as StorageBlock * one_mask;
-        // For example, if we set bits_per_elem to 4:
-        // one_mask  = 0x11111111;
-        // high_mask = 0x88888888;
-        // x_mask    = 0x33333333; (when x = 3)
-
-        // https://graphics.stanford.edu/~seander/bithacks.html
-        // #define haszero(v) (((v) - 0x01010101UL) & ~(v) & 0x80808080UL)
-        // #define hasvalue(x,n) (haszero((x) ^ (~0UL/255 * (n))))
-        let haszero = |v: StorageBlock| -> bool {
-            ((v.wrapping_sub(one_mask as StorageBlock)) & !v & high_mask) != 0
-        };
-
-        for i in 0..last_block {
-            if haszero(self.storage[i] ^ x_mask) {
-                return true;
-            }
-        }
-
-        // Check last block
-        // This branch can be safely removed, as haszero(0xFFFFFFFF) will return false
-        // So it could be replaced by return haszero(... | last_block_mask)
-        if last_elem_shift != 0 {
-            // Mask which sets the remaining elements to all ones, so they
-            // do not affect the result of haszero
-            let last_block_mask = !0 << (last_elem_shift);
-            if haszero((self.storage[last_block] ^ x_mask) | last_block_mask) {
-                return true;
-            }
-        }
-
-        false
+        packing::any(&self.storage, Self::BITS_PER_ELEM, self.len(), x.to_discr())
     }
 
     /// Check whether all of the elements are equal to `x`.
@@ -843,45 +1125,34 @@ impl<T: EnumLike> EnumVec<T> {
     /// assert_eq!(ev.all(None), false);
     /// ```
     pub fn all(&self, x: T) -> bool {
-        if self.is_empty() {
-            return true;
-        }
-        let (last_block, last_elem_shift) = Self::block_index(self.len());
-
-        // Mask the unused bits to 0. Eg. if BITS_PER_ELEM = 15 and
-        // STORAGE_BLOCK_SIZE = 30, the 2 most significant bits are unused.
-        let valid_mask = !0 >> (STORAGE_BLOCK_SIZE % Self::BITS_PER_ELEM);
-        // 2^BITS_PER_ELEM - 1
-        let num_variants_p2 = Self::ELEMENT_MASK;
-        // A mask where all the elements have discriminant value of 1
-        let one_mask = (!0 / num_variants_p2) >> (STORAGE_BLOCK_SIZE % Self::BITS_PER_ELEM);
-        // A mask where all the elements have discriminant value of x
-        let x = x.to_discr();
-        let x_mask = x as StorageBlock * one_mask;
-        // For example, if we set bits_per_elem to 4:
-        // one_mask  = 0x11111111;
-        // x_mask    = 0x33333333; (when x = 3)
-        // Then checking for all(x == 3) is as easy as storage[i] == x_mask
-
-        // Iterate in reverse order because we may have loaded the last block
-        // in the previous block of code, this way we can reuse the cache.
-        for i in 0..last_block {
-            if self.storage[i] & valid_mask != x_mask {
-                return false;
-            }
-        }
+        packing::all(&self.storage, Self::BITS_PER_ELEM, self.len(), x.to_discr())
+    }
 
-        // Check last block
-        // This branch can be safely removed, but I am not sure about the performance
-        // differences.
-        if last_elem_shift != 0 {
-            let last_block_mask = !(!0 << (last_elem_shift));
-            if self.storage[last_block] & last_block_mask != x_mask & last_block_mask {
-                return false;
-            }
-        }
+    /// Count the elements equal to `x`.
+    /// This method uses the same bithacks as `any` and `all`.
+    /// ```
+    /// use enum_vec::vec_u16::EnumVec;
+    ///
+    /// let mut ev: EnumVec<_> = vec![None; 200].into();
+    /// ev.set(3, Some(true));
+    /// ev.set(100, Some(true));
+    /// assert_eq!(ev.count(None), 198);
+    /// assert_eq!(ev.count(Some(true)), 2);
+    /// assert_eq!(ev.count(Some(false)), 0);
+    /// ```
+    pub fn count(&self, x: T) -> usize {
+        self.count_raw_between(x.to_discr(), 0, self.len())
+    }
 
-        true
+    // Count the elements equal to the discriminant x, starting from the
+    // first element of block first_block, up to element end (exclusive).
+    pub(crate) fn count_raw_between(
+        &self,
+        x: usize,
+        first_block: usize,
+        end: usize,
+    ) -> usize {
+        packing::count_between(&self.storage, Self::BITS_PER_ELEM, x, first_block, end)
     }
 }
 
@@ -1002,6 +1273,29 @@ impl<'a, T: EnumLike> Iterator for EnumVecIterMut<'a, T> {
 }
 */
 
+/// Iterator over the runs of equal elements of an `EnumVec`.
+///
+/// See [`EnumVec::runs`](struct.EnumVec.html#method.runs).
+pub struct Runs<'a, T: 'a + EnumLike> {
+    v: &'a EnumVec<T>,
+    idx: usize,
+}
+
+impl<'a, T: EnumLike> Iterator for Runs<'a, T> {
+    type Item = (T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.v.len() {
+            return None;
+        }
+        let x = self.v.get(self.idx).unwrap();
+        let n = self.v.run_length(self.idx);
+        self.idx += n;
+
+        Some((x, n))
+    }
+}
+
 /// Iterator over &EnumVec
 pub struct EnumVecIter<'a, T: 'a + EnumLike> {
     v: &'a EnumVec<T>,
@@ -1089,6 +1383,30 @@ impl<T: EnumLike> Rem<usize> for EnumVec<T> {
 }
 */
 
+impl<T: EnumTable> Index<usize> for EnumVec<T> {
+    type Output = T;
+
+    /// Returns a reference to the value in `T::ALL` with the discriminant
+    /// of the element at position `i`.
+    /// ```
+    /// use enum_vec::vec_u16::EnumVec;
+    ///
+    /// let ev: EnumVec<_> = vec![true, false, true].into();
+    /// assert_eq!(ev[1], false);
+    /// match &ev[2] {
+    ///     true => {}
+    ///     false => unreachable!(),
+    /// }
+    /// ```
+    fn index(&self, i: usize) -> &T {
+        if i >= self.len() {
+            panic!("index out of bounds: {} >= {}", i, self.len());
+        }
+
+        &T::ALL[unsafe { self.get_raw_unchecked(i) }]
+    }
+}
+
 impl<T: EnumLike> PartialEq for EnumVec<T> {
     fn eq(&self, other: &EnumVec<T>) -> bool {
         // TODO: efficient block-wise comparison
@@ -1131,6 +1449,307 @@ impl<T: EnumLike> Hash for EnumVec<T> {
 // Useful alias?
 /// Alias for `EnumVec<bool>`
 pub type BitVec = EnumVec<bool>;
+
+// Bit vector operations. They all work on whole storage blocks, treating the
+// BitVec as a big little-endian integer: the element at index 0 is the least
+// significant bit.
+impl EnumVec<bool> {
+    /// Returns the number of elements equal to `true`.
+    /// ```
+    /// use enum_vec::vec_u16::BitVec;
+    ///
+    /// let mut bv = BitVec::from_elem(true, 100);
+    /// bv.set(3, false);
+    /// assert_eq!(bv.count_ones(), 99);
+    /// assert_eq!(bv.count_zeros(), 1);
+    /// ```
+    pub fn count_ones(&self) -> usize {
+        let num_blocks = Self::blocks_for_elements(self.len());
+        (0..num_blocks)
+            .map(|b| self.masked_block(b).count_ones() as usize)
+            .sum()
+    }
+
+    /// Returns the number of elements equal to `false`.
+    pub fn count_zeros(&self) -> usize {
+        self.len() - self.count_ones()
+    }
+
+    /// Returns the number of consecutive `false` elements at the end of the
+    /// vector, the most significant bits.
+    /// ```
+    /// use enum_vec::vec_u16::BitVec;
+    ///
+    /// let mut bv = BitVec::from_elem(false, 100);
+    /// assert_eq!(bv.leading_zeros(), 100);
+    /// bv.set(60, true);
+    /// assert_eq!(bv.leading_zeros(), 39);
+    /// ```
+    pub fn leading_zeros(&self) -> usize {
+        let num_blocks = Self::blocks_for_elements(self.len());
+        // The number of unused bits in the last block, which must not be
+        // counted as zeros
+        let unused_bits = num_blocks * STORAGE_BLOCK_SIZE - self.len();
+        let mut zeros = 0;
+        for b in (0..num_blocks).rev() {
+            let block = self.masked_block(b);
+            if block != 0 {
+                zeros += block.leading_zeros() as usize;
+                return zeros - unused_bits;
+            }
+            zeros += STORAGE_BLOCK_SIZE;
+        }
+
+        self.len()
+    }
+
+    /// Returns the number of consecutive `false` elements at the start of the
+    /// vector, the least significant bits.
+    /// ```
+    /// use enum_vec::vec_u16::BitVec;
+    ///
+    /// let mut bv = BitVec::from_elem(false, 100);
+    /// assert_eq!(bv.trailing_zeros(), 100);
+    /// bv.set(60, true);
+    /// assert_eq!(bv.trailing_zeros(), 60);
+    /// ```
+    pub fn trailing_zeros(&self) -> usize {
+        self.first_one().unwrap_or(self.len())
+    }
+
+    /// Returns the index of the first element equal to `true`.
+    pub fn first_one(&self) -> Option<usize> {
+        self.iter_ones().next()
+    }
+
+    /// Iterator over the indices of the elements equal to `true`, in
+    /// ascending order.
+    /// ```
+    /// use enum_vec::vec_u16::BitVec;
+    ///
+    /// let bv: BitVec = vec![false, true, true, false, true].into();
+    /// let ones: Vec<_> = bv.iter_ones().collect();
+    /// assert_eq!(ones, vec![1, 2, 4]);
+    /// ```
+    pub fn iter_ones(&self) -> IterOnes<'_> {
+        let block = if self.is_empty() { 0 } else { self.masked_block(0) };
+        IterOnes {
+            v: self,
+            block_idx: 0,
+            block,
+        }
+    }
+
+    // Apply f to every pair of blocks, the result is stored in self.
+    fn zip_blocks_with<F>(&mut self, other: &Self, f: F)
+    where
+        F: Fn(StorageBlock, StorageBlock) -> StorageBlock,
+    {
+        assert_eq!(self.len(), other.len(), "length mismatch");
+        let num_blocks = Self::blocks_for_elements(self.len());
+        for b in 0..num_blocks {
+            self.storage[b] = f(self.storage[b], other.storage[b]);
+        }
+    }
+
+    // Move the element at index i to index i + n, filling with false
+    fn shift_up(&mut self, n: usize) {
+        let num_blocks = Self::blocks_for_elements(self.len());
+        let block_shift = cmp::min(n / STORAGE_BLOCK_SIZE, num_blocks);
+        let bit_shift = n % STORAGE_BLOCK_SIZE;
+        for b in (block_shift..num_blocks).rev() {
+            let mut block = self.storage[b - block_shift] << bit_shift;
+            if bit_shift != 0 && b > block_shift {
+                block |= self.storage[b - block_shift - 1]
+                    >> (STORAGE_BLOCK_SIZE - bit_shift);
+            }
+            self.storage[b] = block;
+        }
+        for b in 0..block_shift {
+            self.storage[b] = 0;
+        }
+    }
+
+    // Move the element at index i to index i - n, filling with false
+    fn shift_down(&mut self, n: usize) {
+        let num_blocks = Self::blocks_for_elements(self.len());
+        if num_blocks == 0 {
+            return;
+        }
+        // Clear the unused bits of the last block, as they will be shifted
+        // into valid positions
+        self.storage[num_blocks - 1] = self.masked_block(num_blocks - 1);
+        let block_shift = cmp::min(n / STORAGE_BLOCK_SIZE, num_blocks);
+        let bit_shift = n % STORAGE_BLOCK_SIZE;
+        for b in 0..(num_blocks - block_shift) {
+            let mut block = self.storage[b + block_shift] >> bit_shift;
+            if bit_shift != 0 && b + block_shift + 1 < num_blocks {
+                block |= self.storage[b + block_shift + 1]
+                    << (STORAGE_BLOCK_SIZE - bit_shift);
+            }
+            self.storage[b] = block;
+        }
+        for b in (num_blocks - block_shift)..num_blocks {
+            self.storage[b] = 0;
+        }
+    }
+}
+
+/// Iterator over the indices of the `true` elements of a `BitVec`.
+///
+/// See [`BitVec::iter_ones`](struct.EnumVec.html#method.iter_ones).
+pub struct IterOnes<'a> {
+    v: &'a BitVec,
+    block_idx: usize,
+    // The remaining bits of the current block
+    block: StorageBlock,
+}
+
+impl<'a> Iterator for IterOnes<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let num_blocks = BitVec::blocks_for_elements(self.v.len());
+        loop {
+            if self.block != 0 {
+                let bit = self.block.trailing_zeros() as usize;
+                // Clear the lowest set bit
+                self.block &= self.block - 1;
+                return Some(self.block_idx * STORAGE_BLOCK_SIZE + bit);
+            }
+            if self.block_idx + 1 >= num_blocks {
+                return None;
+            }
+            self.block_idx += 1;
+            self.block = self.v.masked_block(self.block_idx);
+        }
+    }
+}
+
+// Implement BitOp for every combination of BitVec and &BitVec, plus the
+// BitOpAssign variants. Both vectors must have the same length.
+macro_rules! bitvec_binary_op {
+    ($Op:ident, $op:ident, $OpAssign:ident, $op_assign:ident, $f:expr) => {
+        impl<'a> $OpAssign<&'a BitVec> for BitVec {
+            fn $op_assign(&mut self, rhs: &'a BitVec) {
+                self.zip_blocks_with(rhs, $f);
+            }
+        }
+
+        impl $OpAssign for BitVec {
+            fn $op_assign(&mut self, rhs: BitVec) {
+                self.$op_assign(&rhs);
+            }
+        }
+
+        impl<'a> $Op<&'a BitVec> for BitVec {
+            type Output = BitVec;
+
+            fn $op(mut self, rhs: &'a BitVec) -> BitVec {
+                self.$op_assign(rhs);
+                self
+            }
+        }
+
+        impl $Op for BitVec {
+            type Output = BitVec;
+
+            fn $op(mut self, rhs: BitVec) -> BitVec {
+                self.$op_assign(&rhs);
+                self
+            }
+        }
+
+        impl<'a, 'b> $Op<&'b BitVec> for &'a BitVec {
+            type Output = BitVec;
+
+            fn $op(self, rhs: &'b BitVec) -> BitVec {
+                let mut x = self.clone();
+                x.$op_assign(rhs);
+                x
+            }
+        }
+    };
+}
+
+bitvec_binary_op!(BitAnd, bitand, BitAndAssign, bitand_assign, |a, b| a & b);
+bitvec_binary_op!(BitOr, bitor, BitOrAssign, bitor_assign, |a, b| a | b);
+bitvec_binary_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, |a, b| a ^ b);
+
+/// Element-wise negation.
+/// ```
+/// use enum_vec::vec_u16::BitVec;
+///
+/// let a: BitVec = vec![true, false, false].into();
+/// let b: BitVec = vec![true, true, false].into();
+/// assert_eq!((&a & &b).to_vec(), vec![true, false, false]);
+/// assert_eq!((&a | &b).to_vec(), vec![true, true, false]);
+/// assert_eq!((&a ^ &b).to_vec(), vec![false, true, false]);
+/// assert_eq!((!a).to_vec(), vec![false, true, true]);
+/// ```
+impl Not for BitVec {
+    type Output = BitVec;
+
+    fn not(mut self) -> BitVec {
+        let num_blocks = Self::blocks_for_elements(self.len());
+        for b in 0..num_blocks {
+            self.storage[b] = !self.storage[b];
+        }
+        self
+    }
+}
+
+impl Not for &BitVec {
+    type Output = BitVec;
+
+    fn not(self) -> BitVec {
+        !self.clone()
+    }
+}
+
+/// Shift the elements towards the end of the vector, `v[i + n] = v[i]`.
+/// The length does not change: the last `n` elements are lost, and the first
+/// `n` elements are set to `false`.
+/// ```
+/// use enum_vec::vec_u16::BitVec;
+///
+/// let a: BitVec = vec![true, true, false, true].into();
+/// assert_eq!((a.clone() << 1).to_vec(), vec![false, true, true, false]);
+/// assert_eq!((a.clone() >> 1).to_vec(), vec![true, false, true, false]);
+/// assert_eq!((a << 10).count_ones(), 0);
+/// ```
+impl ShlAssign<usize> for BitVec {
+    fn shl_assign(&mut self, n: usize) {
+        self.shift_up(n);
+    }
+}
+
+impl Shl<usize> for BitVec {
+    type Output = BitVec;
+
+    fn shl(mut self, n: usize) -> BitVec {
+        self.shift_up(n);
+        self
+    }
+}
+
+/// Shift the elements towards the start of the vector, `v[i] = v[i + n]`.
+/// The length does not change: the first `n` elements are lost, and the last
+/// `n` elements are set to `false`.
+impl ShrAssign<usize> for BitVec {
+    fn shr_assign(&mut self, n: usize) {
+        self.shift_down(n);
+    }
+}
+
+impl Shr<usize> for BitVec {
+    type Output = BitVec;
+
+    fn shr(mut self, n: usize) -> BitVec {
+        self.shift_down(n);
+        self
+    }
+}
 // N-bit vec (currently unimplemented)
 // needs const generics
 //pub type NBitVec<N> = EnumVec<[bool; N]>;
@@ -1345,6 +1964,77 @@ mod tests {
         assert_eq!(got, 0x0B);
     }
 
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    struct WideBool(bool);
+
+    unsafe impl EnumLike for WideBool {
+        const NUM_VARIANTS: usize = 2;
+        const RESERVED_BITS: usize = 4;
+        fn to_discr(self) -> usize {
+            self.0 as usize
+        }
+        fn from_discr(x: usize) -> Self {
+            WideBool(x != 0)
+        }
+    }
+
+    #[test]
+    fn reserved_bits() {
+        assert_eq!(EnumVec::<WideBool>::BITS_PER_ELEM, 4);
+        // Reserving less bits than needed is an error, so 0 means the minimum
+        assert_eq!(EnumVec::<bool>::BITS_PER_ELEM, 1);
+        let mut v = EnumVec::new();
+        v.push(WideBool(true));
+        v.push(WideBool(true));
+        // The second element starts at bit 4
+        assert_eq!((v.storage()[0] >> 4) & 0xF, 1);
+        v.push(WideBool(false));
+        assert_eq!(v.count(WideBool(true)), 2);
+        assert!(v.any(WideBool(false)));
+        assert!(!v.all(WideBool(true)));
+        assert_eq!(v.to_vec(), vec![WideBool(true), WideBool(true), WideBool(false)]);
+    }
+
+    // One element per block, the largest RESERVED_BITS for this storage
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    struct FullBlock(bool);
+
+    unsafe impl EnumLike for FullBlock {
+        const NUM_VARIANTS: usize = 2;
+        const RESERVED_BITS: usize = STORAGE_BLOCK_SIZE;
+        fn to_discr(self) -> usize {
+            self.0 as usize
+        }
+        fn from_discr(x: usize) -> Self {
+            FullBlock(x != 0)
+        }
+    }
+
+    #[test]
+    fn reserved_bits_full_block() {
+        assert_eq!(EnumVec::<FullBlock>::BITS_PER_ELEM, STORAGE_BLOCK_SIZE);
+        let mut v = EnumVec::new();
+        let mut expected = vec![];
+        for i in 0..10 {
+            v.push(FullBlock(i % 3 == 0));
+            expected.push(FullBlock(i % 3 == 0));
+        }
+        v.insert(2, FullBlock(true));
+        expected.insert(2, FullBlock(true));
+        assert_eq!(v.remove(5), expected.remove(5));
+        v.set(0, FullBlock(false));
+        expected[0] = FullBlock(false);
+        assert_eq!(v.to_vec(), expected);
+        // One element per block
+        assert_eq!((v.storage()[0], v.storage()[2]), (0, 1));
+        assert_eq!(v.count(FullBlock(true)), expected.iter().filter(|x| x.0).count());
+        assert!(v.any(FullBlock(false)));
+        assert!(!v.all(FullBlock(true)));
+        v.resize(20, FullBlock(true));
+        v.retain(|x| x.0);
+        assert!(v.all(FullBlock(true)));
+    }
+
     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     struct TwoDigits {
         tens: Digit,
@@ -1516,4 +2206,88 @@ mod tests {
         assert_eq!(v.any(Some(false)), false);
         assert_eq!(v.any(Some(true)), false);
     }
+
+    #[test]
+    fn count_wasted_bits() {
+        // TwoDigits uses 7 bits, so there are wasted bits in every block
+        let mut ev = EnumVec::new();
+        let mut v = Vec::new();
+        for i in 0..200u8 {
+            let d = TwoDigits {
+                tens: Digit { x: i % 3 },
+                ones: Digit { x: i % 2 },
+            };
+            ev.push(d);
+            v.push(d);
+        }
+        for x in &v[..6] {
+            let expected = v.iter().filter(|y| *y == x).count();
+            assert_eq!(ev.count(*x), expected);
+        }
+        assert_eq!(ev.count(TwoDigits { tens: Digit { x: 9 }, ones: Digit { x: 9 } }), 0);
+    }
+
+    // Pseudo-random bits, so the tests do not depend on the rand crate
+    fn random_bits(n: usize, seed: u32) -> Vec<bool> {
+        let mut x = seed;
+        (0..n).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x & 1 == 1
+        }).collect()
+    }
+
+    #[test]
+    fn bitvec_ops() {
+        for &n in &[0, 1, 31, 32, 33, 100, 129] {
+            let a = random_bits(n, 1);
+            let b = random_bits(n, 2);
+            let ea: BitVec = a.clone().into();
+            let eb: BitVec = b.clone().into();
+            let and: Vec<_> = a.iter().zip(&b).map(|(x, y)| x & y).collect();
+            let or: Vec<_> = a.iter().zip(&b).map(|(x, y)| x | y).collect();
+            let xor: Vec<_> = a.iter().zip(&b).map(|(x, y)| x ^ y).collect();
+            let not: Vec<_> = a.iter().map(|x| !x).collect();
+            assert_eq!((&ea & &eb).to_vec(), and);
+            assert_eq!((&ea | &eb).to_vec(), or);
+            assert_eq!((&ea ^ &eb).to_vec(), xor);
+            assert_eq!((!&ea).to_vec(), not);
+
+            let ones: Vec<_> = (0..n).filter(|&i| a[i]).collect();
+            assert_eq!(ea.iter_ones().collect::<Vec<_>>(), ones);
+            assert_eq!(ea.count_ones(), ones.len());
+            assert_eq!((!&ea).count_ones(), n - ones.len());
+            assert_eq!(ea.first_one(), ones.first().cloned());
+            let leading = a.iter().rev().take_while(|x| !**x).count();
+            assert_eq!(ea.leading_zeros(), leading);
+            assert_eq!((!&ea).trailing_zeros(),
+                       a.iter().take_while(|x| **x).count());
+        }
+    }
+
+    #[test]
+    fn bitvec_shifts() {
+        let n = 150;
+        let a = random_bits(n, 3);
+        for &shift in &[0, 1, 5, 31, 32, 33, 64, 100, 149, 150, 1000] {
+            let ea: BitVec = a.clone().into();
+            let mut shl = vec![false; n];
+            let mut shr = vec![false; n];
+            for i in 0..n {
+                if i + shift < n {
+                    shl[i + shift] = a[i];
+                    shr[i] = a[i + shift];
+                }
+            }
+            assert_eq!((ea.clone() << shift).to_vec(), shl);
+            assert_eq!((ea >> shift).to_vec(), shr);
+        }
+
+        // The unused bits of the last block must not be shifted in
+        let mut ev = BitVec::from_elem(true, 40);
+        ev.truncate(20);
+        ev >>= 5;
+        assert_eq!(ev.count_ones(), 15);
+    }
 }
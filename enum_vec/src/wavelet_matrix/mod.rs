@@ -0,0 +1,360 @@
+use enum_like::EnumLike;
+use rank_select::RankSelect;
+use std::marker::PhantomData;
+use std::ops::{Bound, Range, RangeBounds};
+use vec_u32::{BitVec, EnumVec};
+
+/// A wavelet matrix, a sequence which supports rank, select and range
+/// queries in O(log NUM_VARIANTS) time.
+///
+/// The alphabet is the set of discriminants of `T`, so each element is
+/// represented by the minimum number of bits needed to store every
+/// discriminant, from the most significant to the least significant. Extra
+/// bits reserved using `EnumLike::RESERVED_BITS` are ignored. Each level of
+/// the matrix is a `BitVec` with the corresponding bit of every element,
+/// stably partitioned by the bits of the previous levels.
+///
+/// Queries about the order of the elements, like `quantile` and
+/// `range_count`, use the order of the discriminants.
+///
+/// ```
+/// use enum_vec::EnumVec;
+/// use enum_vec::WaveletMatrix;
+///
+/// let ev: EnumVec<_> = vec![Some(true), None, Some(false), None].into();
+/// let wm = WaveletMatrix::new(&ev);
+/// assert_eq!(wm.access(2), Some(Some(false)));
+/// assert_eq!(wm.rank(None, 3), 1);
+/// assert_eq!(wm.select(None, 1), Some(3));
+/// // Option<bool> discriminants: Some(false) < Some(true) < None
+/// assert_eq!(wm.quantile(0..4, 0), Some(Some(false)));
+/// assert_eq!(wm.range_count(0..4, Some(true)..), 3);
+/// ```
+#[derive(Clone, Debug)]
+pub struct WaveletMatrix<T: EnumLike> {
+    // levels[0] holds the most significant bit
+    levels: Vec<RankSelect<bool>>,
+    // Number of zeros in each level
+    zeros: Vec<usize>,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: EnumLike> WaveletMatrix<T> {
+    /// Build a wavelet matrix with the elements of `v`, in
+    /// O(n log NUM_VARIANTS) time.
+    pub fn new(v: &EnumVec<T>) -> Self {
+        let num_levels = EnumVec::<T>::MIN_BITS_PER_ELEM;
+        let mut levels = Vec::with_capacity(num_levels);
+        let mut zeros = Vec::with_capacity(num_levels);
+        let mut cur: Vec<usize> = v.iter().map(|x| x.to_discr()).collect();
+        let mut next_zeros = Vec::with_capacity(v.len());
+        let mut next_ones = Vec::with_capacity(v.len());
+
+        for l in 0..num_levels {
+            let bit = num_levels - 1 - l;
+            let mut bv = BitVec::with_capacity(v.len());
+            for &x in &cur {
+                let b = (x >> bit) & 1 == 1;
+                bv.push(b);
+                if b {
+                    next_ones.push(x);
+                } else {
+                    next_zeros.push(x);
+                }
+            }
+            zeros.push(next_zeros.len());
+            levels.push(RankSelect::new(bv));
+            cur.clear();
+            cur.append(&mut next_zeros);
+            cur.append(&mut next_ones);
+        }
+
+        Self {
+            levels,
+            zeros,
+            len: v.len(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Follow position i from level l to level l + 1
+    fn next_pos(&self, l: usize, i: usize, bit: bool) -> usize {
+        let ones = self.levels[l].rank(true, i);
+        if bit {
+            self.zeros[l] + ones
+        } else {
+            i - ones
+        }
+    }
+
+    /// Returns the element at position `i`.
+    pub fn access(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+        let mut i = i;
+        let mut x = 0;
+        for l in 0..self.levels.len() {
+            let bit = self.levels[l].get(i).unwrap();
+            x = (x << 1) | bit as usize;
+            i = self.next_pos(l, i, bit);
+        }
+
+        Some(T::from_discr(x))
+    }
+
+    /// Returns the number of elements equal to `x` in the range `0..i`.
+    ///
+    /// # Panics
+    ///
+    /// If `i > self.len()`.
+    pub fn rank(&self, x: T, i: usize) -> usize {
+        assert!(i <= self.len, "index out of bounds: {} > {}", i, self.len);
+        let (start, end) = self.descend(x.to_discr(), 0..i);
+        end - start
+    }
+
+    // Follow the range of positions from the first to the last level, along
+    // the path of the discriminant x
+    fn descend(&self, x: usize, range: Range<usize>) -> (usize, usize) {
+        let num_levels = self.levels.len();
+        let (mut start, mut end) = (range.start, range.end);
+        for l in 0..num_levels {
+            let bit = (x >> (num_levels - 1 - l)) & 1 == 1;
+            start = self.next_pos(l, start, bit);
+            end = self.next_pos(l, end, bit);
+        }
+
+        (start, end)
+    }
+
+    /// Returns the index of the `k`-th element equal to `x`, starting from
+    /// `k = 0`. Returns `None` if there are not enough elements.
+    pub fn select(&self, x: T, k: usize) -> Option<usize> {
+        let x = x.to_discr();
+        let num_levels = self.levels.len();
+        let (start, end) = self.descend(x, 0..self.len);
+        if k >= end - start {
+            return None;
+        }
+        // Go back up, from the last level to the first one
+        let mut pos = start + k;
+        for l in (0..num_levels).rev() {
+            let bit = (x >> (num_levels - 1 - l)) & 1 == 1;
+            pos = if bit {
+                self.levels[l].select(true, pos - self.zeros[l])
+            } else {
+                self.levels[l].select(false, pos)
+            }
+            .unwrap();
+        }
+
+        Some(pos)
+    }
+
+    /// Returns the `k`-th smallest element in the range of positions
+    /// `range`, starting from `k = 0`. Returns `None` if the range has
+    /// `k` or less elements.
+    ///
+    /// ```
+    /// use enum_vec::EnumVec;
+    /// use enum_vec::WaveletMatrix;
+    ///
+    /// let ev: EnumVec<_> = vec![None, Some(true), Some(false)].into();
+    /// let wm = WaveletMatrix::new(&ev);
+    /// // Option<bool> discriminants: Some(false) < Some(true) < None
+    /// assert_eq!(wm.quantile(0..3, 0), Some(Some(false)));
+    /// assert_eq!(wm.quantile(0..2, 0), Some(Some(true)));
+    /// assert_eq!(wm.quantile(1..3, 2), None);
+    /// ```
+    pub fn quantile(&self, range: Range<usize>, k: usize) -> Option<T> {
+        assert!(range.end <= self.len, "range out of bounds");
+        if range.start >= range.end || k >= range.end - range.start {
+            return None;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        let mut k = k;
+        let mut x = 0;
+        for l in 0..self.levels.len() {
+            let ones_start = self.levels[l].rank(true, start);
+            let ones_end = self.levels[l].rank(true, end);
+            let zeros_in_range = (end - start) - (ones_end - ones_start);
+            if k < zeros_in_range {
+                x <<= 1;
+                start -= ones_start;
+                end -= ones_end;
+            } else {
+                k -= zeros_in_range;
+                x = (x << 1) | 1;
+                start = self.zeros[l] + ones_start;
+                end = self.zeros[l] + ones_end;
+            }
+        }
+
+        Some(T::from_discr(x))
+    }
+
+    /// Returns the number of elements in the range of positions `range`
+    /// whose discriminant is inside `values`.
+    pub fn range_count<R>(&self, range: Range<usize>, values: R) -> usize
+    where
+        R: RangeBounds<T>,
+    {
+        assert!(range.end <= self.len, "range out of bounds");
+        if range.start >= range.end {
+            return 0;
+        }
+        let lo = match values.start_bound() {
+            Bound::Included(x) => x.to_discr(),
+            Bound::Excluded(x) => x.to_discr() + 1,
+            Bound::Unbounded => 0,
+        };
+        let hi = match values.end_bound() {
+            Bound::Included(x) => x.to_discr() + 1,
+            Bound::Excluded(x) => x.to_discr(),
+            Bound::Unbounded => T::NUM_VARIANTS,
+        };
+        if lo >= hi {
+            return 0;
+        }
+
+        self.count_less(range.clone(), hi) - self.count_less(range, lo)
+    }
+
+    // Number of elements in range with discriminant < x
+    fn count_less(&self, range: Range<usize>, x: usize) -> usize {
+        let num_levels = self.levels.len();
+        if x >> num_levels != 0 {
+            return range.end - range.start;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        let mut count = 0;
+        for l in 0..num_levels {
+            let bit = (x >> (num_levels - 1 - l)) & 1 == 1;
+            if bit {
+                // All the elements with a 0 here are smaller than x
+                let ones_start = self.levels[l].rank(true, start);
+                let ones_end = self.levels[l].rank(true, end);
+                count += (end - start) - (ones_end - ones_start);
+            }
+            start = self.next_pos(l, start, bit);
+            end = self.next_pos(l, end, bit);
+        }
+
+        count
+    }
+}
+
+impl<'a, T: EnumLike> From<&'a EnumVec<T>> for WaveletMatrix<T> {
+    fn from(v: &'a EnumVec<T>) -> Self {
+        Self::new(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<[bool; 3]> {
+        (0..700usize)
+            .map(|i| <[bool; 3]>::from_discr((i * 5 + i / 7) % 8))
+            .collect()
+    }
+
+    #[test]
+    fn access_rank_select() {
+        let v = sample();
+        let wm = WaveletMatrix::new(&EnumVec::from(v.clone()));
+        for (i, x) in v.iter().enumerate() {
+            assert_eq!(wm.access(i), Some(*x));
+        }
+        assert_eq!(wm.access(v.len()), None);
+        for d in 0..8 {
+            let x = <[bool; 3]>::from_discr(d);
+            let mut seen = 0;
+            for (i, y) in v.iter().enumerate() {
+                assert_eq!(wm.rank(x, i), seen);
+                if *y == x {
+                    assert_eq!(wm.select(x, seen), Some(i));
+                    seen += 1;
+                }
+            }
+            assert_eq!(wm.select(x, seen), None);
+        }
+    }
+
+    #[test]
+    fn quantile_range_count() {
+        let v = sample();
+        let wm = WaveletMatrix::new(&EnumVec::from(v.clone()));
+        for &(a, b) in &[(0, 700), (13, 200), (100, 101), (650, 700)] {
+            let mut sorted: Vec<_> = v[a..b].iter().map(|x| x.to_discr()).collect();
+            sorted.sort();
+            for (k, d) in sorted.iter().enumerate() {
+                assert_eq!(wm.quantile(a..b, k).unwrap().to_discr(), *d);
+            }
+            assert_eq!(wm.quantile(a..b, b - a), None);
+
+            for lo in 0..8 {
+                for hi in lo..8 {
+                    let expected =
+                        sorted.iter().filter(|&&d| d >= lo && d <= hi).count();
+                    let lo = <[bool; 3]>::from_discr(lo);
+                    let hi = <[bool; 3]>::from_discr(hi);
+                    assert_eq!(wm.range_count(a..b, lo..=hi), expected);
+                }
+            }
+            assert_eq!(wm.range_count(a..b, ..), b - a);
+        }
+    }
+
+    #[test]
+    fn non_power_of_two_alphabet() {
+        let v: Vec<_> = (0..300u32).map(|i| match i % 3 {
+            0 => None,
+            1 => Some(false),
+            _ => Some(true),
+        }).collect();
+        let wm = WaveletMatrix::new(&EnumVec::from(v.clone()));
+        assert_eq!(wm.range_count(0..300, Some(true)..), 200);
+        assert_eq!(wm.range_count(0..300, ..None), 200);
+        assert_eq!(wm.quantile(0..300, 299), Some(None));
+        assert_eq!(wm.select(None, 99), Some(297));
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    struct WideBool(bool);
+
+    unsafe impl EnumLike for WideBool {
+        const NUM_VARIANTS: usize = 2;
+        const RESERVED_BITS: usize = 4;
+        fn to_discr(self) -> usize {
+            self.0 as usize
+        }
+        fn from_discr(x: usize) -> Self {
+            WideBool(x != 0)
+        }
+    }
+
+    #[test]
+    fn reserved_bits() {
+        let v: Vec<_> = (0..100usize).map(|i| WideBool(i % 3 == 0)).collect();
+        let wm = WaveletMatrix::new(&EnumVec::from(v.clone()));
+        // One level per bit needed by the variants, not per reserved bit
+        assert_eq!(wm.levels.len(), 1);
+        assert_eq!(wm.access(3), Some(WideBool(true)));
+        assert_eq!(wm.rank(WideBool(true), 100), 34);
+        assert_eq!(wm.quantile(0..100, 99), Some(WideBool(true)));
+    }
+}
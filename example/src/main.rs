@@ -368,4 +368,83 @@ mod tests {
         struct BoolTS<T, S>(bool, T, S);
         check_values_of::<BoolTS<(), Option<()>>>(2 * 1 * 2);
     }
+
+    #[test]
+    fn derive_reserve_bits() {
+        #[derive(Copy, Clone, Debug, PartialEq, EnumLike)]
+        #[enum_like(reserve_bits = 4)]
+        enum Color {
+            Red,
+            Green,
+            Blue,
+        }
+        #[derive(Copy, Clone, Debug, PartialEq, EnumLike)]
+        #[enum_like(reserve_bits = 8)]
+        struct Pair(Color, bool);
+
+        check_values_of::<Color>(3);
+        assert_eq!(Color::RESERVED_BITS, 4);
+        assert_eq!(Pair::RESERVED_BITS, 8);
+        assert_eq!(<(Color, bool)>::RESERVED_BITS, 0);
+
+        // The layout only depends on the reserved bits
+        let mut v = ::enum_vec::EnumVec::new();
+        v.push(Color::Blue);
+        v.push(Color::Green);
+        assert_eq!(v.storage()[0] & 0xFF, 0x12);
+        let mut v = ::enum_vec::EnumVec::new();
+        v.push(Pair(Color::Blue, true));
+        assert_eq!(v.storage()[0] & 0xFF, 0x05);
+    }
+
+    #[test]
+    fn derive_symbol() {
+        use enum_like::EnumSymbol;
+
+        #[derive(Copy, Clone, Debug, PartialEq, EnumLike)]
+        enum Nucleotide {
+            #[enum_like(symbol = 'A')]
+            Adenine,
+            #[enum_like(symbol = 'C')]
+            Cytosine,
+            #[enum_like(symbol = 'G')]
+            Guanine,
+            #[enum_like(symbol = 'T')]
+            Thymine,
+        }
+
+        assert_eq!(Nucleotide::Guanine.symbol(), 'G');
+        let v: ::enum_vec::EnumVec<Nucleotide> = "GATTACA".parse().unwrap();
+        assert_eq!(v.get(1), Some(Nucleotide::Adenine));
+        assert_eq!(v.count(Nucleotide::Thymine), 2);
+        assert_eq!(v.to_string(), "GATTACA");
+        let e = "GATXACA".parse::<::enum_vec::EnumVec<Nucleotide>>();
+        assert_eq!(e.unwrap_err().position(), 3);
+    }
+
+    #[test]
+    fn derive_static_table() {
+        use enum_like::EnumTable;
+
+        #[derive(Copy, Clone, Debug, PartialEq, EnumLike)]
+        #[enum_like(static_table)]
+        enum Direction {
+            North,
+            East,
+            South,
+            West,
+        }
+
+        assert_eq!(
+            Direction::ALL,
+            &[Direction::North, Direction::East, Direction::South, Direction::West]
+        );
+        let v: ::enum_vec::EnumVec<_> = Direction::ALL.iter().cloned().rev().collect();
+        assert_eq!(v[0], Direction::West);
+        let turns = match &v.slice(1..3)[1] {
+            Direction::North | Direction::South => 0,
+            Direction::East | Direction::West => 1,
+        };
+        assert_eq!(turns, 1);
+    }
 }